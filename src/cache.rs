@@ -0,0 +1,265 @@
+use crate::compose::ComposeIndex;
+use crate::composefile;
+use crate::keyboard::{locale, XkbKeymap};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use xkbcommon::xkb;
+
+// Bump when the cache format or what ComposeIndex::build produces changes;
+// older files are then rebuilt
+const CACHE_VERSION: i64 = 2;
+
+// Cached indexes to keep, newest first: one per keymap the user switches
+// between or compares, with room to spare
+const CACHE_LIMIT: usize = 16;
+
+/// Build the index for a keymap, or read it from
+/// $XDG_CACHE_HOME/kbdviz/index/<key>.toml when nothing it depends on
+/// changed: the keymap, the locale and the Compose files
+pub fn index_for(xkb: &XkbKeymap) -> Result<ComposeIndex, String> {
+    let keymap_string = xkb.keymap().get_as_string(xkb::KEYMAP_FORMAT_TEXT_V1);
+    let key = format!("{:016x}", cache_key(&keymap_string));
+    let Some(path) = cache_dir().map(|dir| dir.join(format!("{}.toml", key))) else {
+        return ComposeIndex::build(xkb);
+    };
+
+    if let Some(index) = read(&path) {
+        touch(&path);
+        return Ok(index);
+    }
+
+    let index = ComposeIndex::build(xkb)?;
+    let mut table = toml::Table::new();
+    table.insert("version".to_string(), CACHE_VERSION.into());
+    table.extend(index.to_table());
+    let result = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| write(&path, &table.to_string()));
+    match result {
+        Ok(()) => prune(),
+        Err(e) => eprintln!("Failed to write {}: {}", path.display(), e),
+    }
+    Ok(index)
+}
+
+/// A cached index, if there is one of this version that parses
+fn read(path: &Path) -> Option<ComposeIndex> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    let table: toml::Table = match contents.parse() {
+        Ok(table) => table,
+        Err(e) => {
+            eprintln!("Ignoring {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    if table.get("version").and_then(|v| v.as_integer()) != Some(CACHE_VERSION) {
+        eprintln!("Ignoring {}: written by another version", path.display());
+        return None;
+    }
+    let index = ComposeIndex::from_table(&table);
+    match &index {
+        Some(index) => eprintln!("Loaded cached index with {} base characters from {}", index.count(), path.display()),
+        None => eprintln!("Ignoring {}: unexpected contents", path.display()),
+    }
+    index
+}
+
+/// Write through a temporary file, so another kbdviz reading the cache
+/// never sees half an index
+fn write(path: &Path, contents: &str) -> std::io::Result<()> {
+    // Not .toml, so prune_dir leaves one that is still being written alone
+    let temp = path.with_extension(format!("tmp{}", std::process::id()));
+    let result = std::fs::write(&temp, contents).and_then(|_| std::fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    result
+}
+
+/// Mark a cached index as just used; pruning goes by modification time,
+/// so the ones in use are kept
+fn touch(path: &Path) {
+    let result = std::fs::File::options().write(true).open(path).and_then(|file| file.set_modified(SystemTime::now()));
+    if let Err(e) = result {
+        eprintln!("Failed to touch {}: {}", path.display(), e);
+    }
+}
+
+/// Hash of everything the index depends on: the keymap, the locale (for
+/// key labels and the Compose table) and the Compose files with their
+/// modification times
+fn cache_key(keymap_string: &str) -> u64 {
+    let mut locale = locale().to_string_lossy().into_owned();
+    for var in ["LC_MESSAGES", "LANG"] {
+        locale.push_str(&format!("\n{}={}", var, env::var(var).unwrap_or_default()));
+    }
+    cache_key_with(keymap_string, &locale, &composefile::source_files())
+}
+
+fn cache_key_with(keymap_string: &str, locale: &str, compose_files: &[PathBuf]) -> u64 {
+    let mut input = format!("{}\n{}\n{}", CACHE_VERSION, keymap_string, locale);
+    for path in compose_files {
+        let modified = std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |age| age.as_nanos());
+        input.push_str(&format!("\n{} {}", path.display(), modified));
+    }
+    hash(input.as_bytes())
+}
+
+/// 64-bit FNV-1a; unlike std's hasher it is the same in every build, so
+/// cache file names stay valid across upgrades
+pub fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3))
+}
+
+/// Remove the oldest cached indexes beyond CACHE_LIMIT
+fn prune() {
+    if let Some(dir) = cache_dir() {
+        prune_dir(&dir, CACHE_LIMIT);
+    }
+}
+
+fn prune_dir(dir: &Path, limit: usize) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut files: Vec<(std::time::SystemTime, PathBuf)> = entries
+        .flatten()
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "toml"))
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .collect();
+    files.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    for (_, path) in files.into_iter().skip(limit) {
+        if let Err(e) = std::fs::remove_file(&path) {
+            eprintln!("Failed to remove {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// $XDG_CACHE_HOME/kbdviz/index, or ~/.cache/kbdviz/index
+fn cache_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CACHE_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .map(|dir| dir.join("kbdviz").join("index"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// An empty directory of its own for each test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("kbdviz-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn set_modified(path: &Path, seconds: u64) {
+        let file = std::fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)).unwrap();
+    }
+
+    #[test]
+    fn key_changes_with_the_locale() {
+        let keymap = "xkb_keymap { };";
+        assert_eq!(cache_key_with(keymap, "de_DE.UTF-8", &[]), cache_key_with(keymap, "de_DE.UTF-8", &[]));
+        assert_ne!(cache_key_with(keymap, "de_DE.UTF-8", &[]), cache_key_with(keymap, "fr_FR.UTF-8", &[]));
+    }
+
+    #[test]
+    fn key_changes_when_a_compose_file_changes() {
+        let dir = test_dir("compose-mtime");
+        let compose = dir.join("Compose");
+        std::fs::write(&compose, "<Multi_key> <e> <'> : \"é\"\n").unwrap();
+        set_modified(&compose, 1_000_000);
+        let before = cache_key_with("keymap", "C", std::slice::from_ref(&compose));
+        assert_eq!(cache_key_with("keymap", "C", std::slice::from_ref(&compose)), before);
+        set_modified(&compose, 2_000_000);
+        assert_ne!(cache_key_with("keymap", "C", std::slice::from_ref(&compose)), before);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn read_rejects_another_version() {
+        let dir = test_dir("version");
+        let index = ComposeIndex::from_routes(&[("é", "AltGr-e")]);
+        let write = |version: i64| {
+            let mut table = toml::Table::new();
+            table.insert("version".to_string(), version.into());
+            table.extend(index.to_table());
+            let path = dir.join(format!("{}.toml", version));
+            std::fs::write(&path, table.to_string()).unwrap();
+            path
+        };
+        assert!(read(&write(CACHE_VERSION)).is_some());
+        assert!(read(&write(CACHE_VERSION + 1)).is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn prune_keeps_the_newest_files() {
+        let dir = test_dir("prune");
+        for age in 0..CACHE_LIMIT + 3 {
+            let path = dir.join(format!("{}.toml", age));
+            std::fs::write(&path, "").unwrap();
+            set_modified(&path, 1_000_000 - age as u64);
+        }
+        // Not a cached index
+        std::fs::write(dir.join("notes.txt"), "").unwrap();
+
+        prune_dir(&dir, CACHE_LIMIT);
+        let mut kept: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        kept.sort_by_key(|name| name.trim_end_matches(".toml").parse::<usize>().unwrap_or(usize::MAX));
+        let mut expected: Vec<String> = (0..CACHE_LIMIT).map(|age| format!("{}.toml", age)).collect();
+        expected.push("notes.txt".to_string());
+        assert_eq!(kept, expected);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn write_replaces_the_file_whole() {
+        let dir = test_dir("write");
+        let path = dir.join("index.toml");
+        write(&path, "version = 1").unwrap();
+        write(&path, "version = 2").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "version = 2");
+        // No temporary file left behind
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn touch_keeps_a_used_index_from_pruning() {
+        let dir = test_dir("touch");
+        for age in 0..3 {
+            let path = dir.join(format!("{}.toml", age));
+            std::fs::write(&path, "").unwrap();
+            set_modified(&path, 1_000_000 - age);
+        }
+        touch(&dir.join("2.toml"));
+        prune_dir(&dir, 2);
+        assert!(dir.join("0.toml").exists());
+        assert!(!dir.join("1.toml").exists());
+        assert!(dir.join("2.toml").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
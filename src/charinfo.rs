@@ -0,0 +1,384 @@
+use unicode_general_category::{get_general_category, GeneralCategory};
+use unicode_normalization::UnicodeNormalization;
+
+/// Ways to copy a character besides the literal glyph
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Representation {
+    Literal,      // é
+    CodePoint,    // U+00E9
+    HtmlEntity,   // &eacute;
+    RustEscape,   // \u{e9}
+    JsonEscape,   // \u00e9
+    Url,          // %C3%A9
+    Latex,        // \'e
+}
+
+impl Representation {
+    /// Parse a name as used in the `[copy]` config section
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "literal" => Some(Representation::Literal),
+            "codepoint" => Some(Representation::CodePoint),
+            "html" => Some(Representation::HtmlEntity),
+            "escape" => Some(Representation::RustEscape),
+            "json" => Some(Representation::JsonEscape),
+            "url" => Some(Representation::Url),
+            "latex" => Some(Representation::Latex),
+            _ => None,
+        }
+    }
+
+    /// Short label for on-screen hints
+    pub fn label(self) -> &'static str {
+        match self {
+            Representation::Literal => "char",
+            Representation::CodePoint => "U+",
+            Representation::HtmlEntity => "HTML",
+            Representation::RustEscape => "\\u{}",
+            Representation::JsonEscape => "JSON",
+            Representation::Url => "URL",
+            Representation::Latex => "LaTeX",
+        }
+    }
+
+    pub fn format(self, text: &str) -> String {
+        match self {
+            Representation::Literal => text.to_string(),
+            Representation::CodePoint => text
+                .chars()
+                .map(|c| format!("U+{:04X}", c as u32))
+                .collect::<Vec<_>>()
+                .join(" "),
+            Representation::HtmlEntity => text.chars().map(html_entity).collect(),
+            Representation::RustEscape => text.chars().map(|c| format!("\\u{{{:x}}}", c as u32)).collect(),
+            // UTF-16 code units, so astral characters become surrogate pairs
+            Representation::JsonEscape => text.encode_utf16().map(|unit| format!("\\u{:04x}", unit)).collect(),
+            Representation::Url => percent_encode(text),
+            Representation::Latex => text.chars().map(latex).collect(),
+        }
+    }
+}
+
+/// Unicode metadata shown in the detail panel
+pub struct CharInfo {
+    pub code_point: String,
+    pub name: String,
+    pub category: String,
+    pub decomposition: Option<String>,
+    pub html_entity: String,
+}
+
+impl CharInfo {
+    pub fn new(text: &str) -> Self {
+        let names: Vec<String> = text
+            .chars()
+            .map(|c| unicode_names2::name(c).map_or_else(|| "<unnamed>".to_string(), |n| n.to_string()))
+            .collect();
+        let categories: Vec<String> = text
+            .chars()
+            .map(|c| {
+                let category = get_general_category(c);
+                format!("{} ({})", category_abbreviation(category), category_name(category))
+            })
+            .collect();
+
+        CharInfo {
+            code_point: Representation::CodePoint.format(text),
+            name: names.join(" + "),
+            category: categories.join(", "),
+            decomposition: decomposition(text),
+            html_entity: Representation::HtmlEntity.format(text),
+        }
+    }
+}
+
+/// Whether a character is a combining mark that attaches to the one before it
+pub fn is_combining(c: char) -> bool {
+    matches!(
+        get_general_category(c),
+        GeneralCategory::NonspacingMark | GeneralCategory::SpacingMark | GeneralCategory::EnclosingMark
+    )
+}
+
+/// Text as drawn on screen: a lone combining mark sits on a dotted circle
+/// (◌́) so it is visible on its own
+pub fn display_text(text: &str) -> String {
+    if text.starts_with(is_combining) {
+        format!("◌{}", text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Categories for characters that aren't letters, in the order they are
+/// listed; typing a category name in name search lists all of it
+pub const SYMBOL_CATEGORIES: [&str; 7] = ["emoji", "arrows", "math", "fractions", "currency", "punctuation", "symbols"];
+
+/// Whether text is an emoji that needs a color font
+pub fn is_emoji(text: &str) -> bool {
+    let Some(first) = text.chars().next() else {
+        return false;
+    };
+    matches!(first as u32, 0x1F000..=0x1FAFF) || text.contains('\u{FE0F}')
+}
+
+/// Which of SYMBOL_CATEGORIES a non-letter belongs to
+pub fn symbol_category(text: &str) -> &'static str {
+    use GeneralCategory::*;
+    let Some(first) = text.chars().next() else {
+        return "symbols";
+    };
+    if is_emoji(text) {
+        return "emoji";
+    }
+    match first as u32 {
+        0x2190..=0x21FF | 0x27F0..=0x27FF | 0x2900..=0x297F | 0x2B00..=0x2BFF => return "arrows",
+        0xBC..=0xBE | 0x2150..=0x215F | 0x2189 => return "fractions",
+        _ => {}
+    }
+    match get_general_category(first) {
+        MathSymbol => "math",
+        CurrencySymbol => "currency",
+        ConnectorPunctuation | DashPunctuation | OpenPunctuation | ClosePunctuation | InitialPunctuation
+        | FinalPunctuation | OtherPunctuation => "punctuation",
+        _ => "symbols",
+    }
+}
+
+/// NFD decomposition like "e + ◌̈ (U+0065 U+0308)", None if the text doesn't decompose
+fn decomposition(text: &str) -> Option<String> {
+    let nfd: String = text.nfd().collect();
+    if nfd == text {
+        return None;
+    }
+
+    let parts: Vec<String> = nfd
+        .chars()
+        .map(|c| display_text(&c.to_string()))
+        .collect();
+    Some(format!("{} ({})", parts.join(" + "), Representation::CodePoint.format(&nfd)))
+}
+
+/// Named HTML entity if there is a common one, numeric reference otherwise
+fn html_entity(c: char) -> String {
+    let named = match c {
+        '&' => "amp", '<' => "lt", '>' => "gt", '"' => "quot", '\u{a0}' => "nbsp",
+        '¡' => "iexcl", '¢' => "cent", '£' => "pound", '¤' => "curren", '¥' => "yen",
+        '¦' => "brvbar", '§' => "sect", '¨' => "uml", '©' => "copy", 'ª' => "ordf",
+        '«' => "laquo", '¬' => "not", '®' => "reg", '¯' => "macr", '°' => "deg",
+        '±' => "plusmn", '²' => "sup2", '³' => "sup3", '´' => "acute", 'µ' => "micro",
+        '¶' => "para", '·' => "middot", '¸' => "cedil", '¹' => "sup1", 'º' => "ordm",
+        '»' => "raquo", '¼' => "frac14", '½' => "frac12", '¾' => "frac34", '¿' => "iquest",
+        'À' => "Agrave", 'Á' => "Aacute", 'Â' => "Acirc", 'Ã' => "Atilde", 'Ä' => "Auml",
+        'Å' => "Aring", 'Æ' => "AElig", 'Ç' => "Ccedil", 'È' => "Egrave", 'É' => "Eacute",
+        'Ê' => "Ecirc", 'Ë' => "Euml", 'Ì' => "Igrave", 'Í' => "Iacute", 'Î' => "Icirc",
+        'Ï' => "Iuml", 'Ð' => "ETH", 'Ñ' => "Ntilde", 'Ò' => "Ograve", 'Ó' => "Oacute",
+        'Ô' => "Ocirc", 'Õ' => "Otilde", 'Ö' => "Ouml", '×' => "times", 'Ø' => "Oslash",
+        'Ù' => "Ugrave", 'Ú' => "Uacute", 'Û' => "Ucirc", 'Ü' => "Uuml", 'Ý' => "Yacute",
+        'Þ' => "THORN", 'ß' => "szlig", 'à' => "agrave", 'á' => "aacute", 'â' => "acirc",
+        'ã' => "atilde", 'ä' => "auml", 'å' => "aring", 'æ' => "aelig", 'ç' => "ccedil",
+        'è' => "egrave", 'é' => "eacute", 'ê' => "ecirc", 'ë' => "euml", 'ì' => "igrave",
+        'í' => "iacute", 'î' => "icirc", 'ï' => "iuml", 'ð' => "eth", 'ñ' => "ntilde",
+        'ò' => "ograve", 'ó' => "oacute", 'ô' => "ocirc", 'õ' => "otilde", 'ö' => "ouml",
+        '÷' => "divide", 'ø' => "oslash", 'ù' => "ugrave", 'ú' => "uacute", 'û' => "ucirc",
+        'ü' => "uuml", 'ý' => "yacute", 'þ' => "thorn", 'ÿ' => "yuml",
+        // Latin Extended-A (HTML5)
+        'Ā' => "Amacr", 'ā' => "amacr", 'Ă' => "Abreve", 'ă' => "abreve", 'Ą' => "Aogon",
+        'ą' => "aogon", 'Ć' => "Cacute", 'ć' => "cacute", 'Č' => "Ccaron", 'č' => "ccaron",
+        'Ď' => "Dcaron", 'ď' => "dcaron", 'Ē' => "Emacr", 'ē' => "emacr", 'Ė' => "Edot",
+        'ė' => "edot", 'Ę' => "Eogon", 'ę' => "eogon", 'Ě' => "Ecaron", 'ě' => "ecaron",
+        'Ğ' => "Gbreve", 'ğ' => "gbreve", 'Ī' => "Imacr", 'ī' => "imacr", 'Į' => "Iogon",
+        'į' => "iogon", 'Ł' => "Lstrok", 'ł' => "lstrok", 'Ń' => "Nacute", 'ń' => "nacute",
+        'Ň' => "Ncaron", 'ň' => "ncaron", 'Ō' => "Omacr", 'ō' => "omacr", 'Ő' => "Odblac",
+        'ő' => "odblac", 'Œ' => "OElig", 'œ' => "oelig", 'Ř' => "Rcaron", 'ř' => "rcaron",
+        'Ś' => "Sacute", 'ś' => "sacute", 'Ş' => "Scedil", 'ş' => "scedil", 'Š' => "Scaron",
+        'š' => "scaron", 'Ť' => "Tcaron", 'ť' => "tcaron", 'Ū' => "Umacr", 'ū' => "umacr",
+        'Ů' => "Uring", 'ů' => "uring", 'Ű' => "Udblac", 'ű' => "udblac", 'Ų' => "Uogon",
+        'ų' => "uogon", 'Ÿ' => "Yuml", 'Ź' => "Zacute", 'ź' => "zacute", 'Ż' => "Zdot",
+        'ż' => "zdot", 'Ž' => "Zcaron", 'ž' => "zcaron",
+        // Punctuation and symbols
+        '–' => "ndash", '—' => "mdash", '‘' => "lsquo", '’' => "rsquo", '‚' => "sbquo",
+        '“' => "ldquo", '”' => "rdquo", '„' => "bdquo", '†' => "dagger", '‡' => "Dagger",
+        '•' => "bull", '…' => "hellip", '‰' => "permil", '‹' => "lsaquo", '›' => "rsaquo",
+        '€' => "euro", '™' => "trade", '←' => "larr", '↑' => "uarr", '→' => "rarr",
+        '↓' => "darr", '≠' => "ne", '≤' => "le", '≥' => "ge", '∞' => "infin",
+        _ => return format!("&#x{:X};", c as u32),
+    };
+    format!("&{};", named)
+}
+
+/// Percent-encode the UTF-8 bytes of everything but unreserved ASCII (RFC 3986)
+fn percent_encode(text: &str) -> String {
+    let mut encoded = String::new();
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// LaTeX text-mode markup for a character, e.g. \'e or \c{c}. Falls back to
+/// the literal character, which works with a UTF-8 input encoding.
+fn latex(c: char) -> String {
+    let special = match c {
+        'ß' => Some("\\ss{}"), 'æ' => Some("\\ae{}"), 'Æ' => Some("\\AE{}"),
+        'œ' => Some("\\oe{}"), 'Œ' => Some("\\OE{}"), 'ø' => Some("\\o{}"),
+        'Ø' => Some("\\O{}"), 'ł' => Some("\\l{}"), 'Ł' => Some("\\L{}"),
+        'å' => Some("\\aa{}"), 'Å' => Some("\\AA{}"), 'ð' => Some("\\dh{}"),
+        'Ð' => Some("\\DH{}"), 'þ' => Some("\\th{}"), 'Þ' => Some("\\TH{}"),
+        '€' => Some("\\texteuro{}"), '£' => Some("\\pounds{}"), '§' => Some("\\S{}"),
+        '¶' => Some("\\P{}"), '©' => Some("\\copyright{}"), '¿' => Some("?`"),
+        '¡' => Some("!`"), '–' => Some("--"), '—' => Some("---"),
+        '&' => Some("\\&"), '%' => Some("\\%"), '$' => Some("\\$"), '#' => Some("\\#"),
+        '_' => Some("\\_"), '{' => Some("\\{"), '}' => Some("\\}"),
+        _ => None,
+    };
+    if let Some(special) = special {
+        return special.to_string();
+    }
+
+    let mut chars = std::iter::once(c).nfd();
+    let Some(base) = chars.next() else {
+        return c.to_string();
+    };
+    let marks: Vec<char> = chars.collect();
+    if marks.is_empty() || !base.is_ascii_alphabetic() {
+        return c.to_string();
+    }
+
+    // Dotless i/j under accents, as LaTeX expects
+    let mut result = match base {
+        'i' => "\\i".to_string(),
+        'j' => "\\j".to_string(),
+        _ => base.to_string(),
+    };
+    for mark in marks {
+        let (command, symbolic) = match mark {
+            '\u{301}' => ("'", true),
+            '\u{300}' => ("`", true),
+            '\u{302}' => ("^", true),
+            '\u{308}' => ("\"", true),
+            '\u{303}' => ("~", true),
+            '\u{304}' => ("=", true),
+            '\u{307}' => (".", true),
+            '\u{327}' => ("c", false),
+            '\u{328}' => ("k", false),
+            '\u{30c}' => ("v", false),
+            '\u{306}' => ("u", false),
+            '\u{30a}' => ("r", false),
+            '\u{30b}' => ("H", false),
+            '\u{323}' => ("d", false),
+            _ => return c.to_string(),
+        };
+        // \'e works for a single letter; anything longer needs braces
+        result = if symbolic && result.len() == 1 {
+            format!("\\{}{}", command, result)
+        } else {
+            format!("\\{}{{{}}}", command, result)
+        };
+    }
+    result
+}
+
+fn category_abbreviation(category: GeneralCategory) -> &'static str {
+    use GeneralCategory::*;
+    match category {
+        UppercaseLetter => "Lu",
+        LowercaseLetter => "Ll",
+        TitlecaseLetter => "Lt",
+        ModifierLetter => "Lm",
+        OtherLetter => "Lo",
+        NonspacingMark => "Mn",
+        SpacingMark => "Mc",
+        EnclosingMark => "Me",
+        DecimalNumber => "Nd",
+        LetterNumber => "Nl",
+        OtherNumber => "No",
+        ConnectorPunctuation => "Pc",
+        DashPunctuation => "Pd",
+        OpenPunctuation => "Ps",
+        ClosePunctuation => "Pe",
+        InitialPunctuation => "Pi",
+        FinalPunctuation => "Pf",
+        OtherPunctuation => "Po",
+        MathSymbol => "Sm",
+        CurrencySymbol => "Sc",
+        ModifierSymbol => "Sk",
+        OtherSymbol => "So",
+        SpaceSeparator => "Zs",
+        LineSeparator => "Zl",
+        ParagraphSeparator => "Zp",
+        Control => "Cc",
+        Format => "Cf",
+        Surrogate => "Cs",
+        PrivateUse => "Co",
+        _ => "Cn",
+    }
+}
+
+fn category_name(category: GeneralCategory) -> &'static str {
+    use GeneralCategory::*;
+    match category {
+        UppercaseLetter => "Uppercase Letter",
+        LowercaseLetter => "Lowercase Letter",
+        TitlecaseLetter => "Titlecase Letter",
+        ModifierLetter => "Modifier Letter",
+        OtherLetter => "Other Letter",
+        NonspacingMark => "Nonspacing Mark",
+        SpacingMark => "Spacing Mark",
+        EnclosingMark => "Enclosing Mark",
+        DecimalNumber => "Decimal Number",
+        LetterNumber => "Letter Number",
+        OtherNumber => "Other Number",
+        ConnectorPunctuation => "Connector Punctuation",
+        DashPunctuation => "Dash Punctuation",
+        OpenPunctuation => "Open Punctuation",
+        ClosePunctuation => "Close Punctuation",
+        InitialPunctuation => "Initial Punctuation",
+        FinalPunctuation => "Final Punctuation",
+        OtherPunctuation => "Other Punctuation",
+        MathSymbol => "Math Symbol",
+        CurrencySymbol => "Currency Symbol",
+        ModifierSymbol => "Modifier Symbol",
+        OtherSymbol => "Other Symbol",
+        SpaceSeparator => "Space Separator",
+        LineSeparator => "Line Separator",
+        ParagraphSeparator => "Paragraph Separator",
+        Control => "Control",
+        Format => "Format",
+        Surrogate => "Surrogate",
+        PrivateUse => "Private Use",
+        _ => "Unassigned",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formats(text: &str) -> Vec<String> {
+        use Representation::*;
+        [CodePoint, HtmlEntity, RustEscape, JsonEscape, Url, Latex].into_iter().map(|r| r.format(text)).collect()
+    }
+
+    #[test]
+    fn formats_every_representation() {
+        assert_eq!(formats("é"), ["U+00E9", "&eacute;", "\\u{e9}", "\\u00e9", "%C3%A9", "\\'e"]);
+        // Dotless i under the accent
+        assert_eq!(formats("ì"), ["U+00EC", "&igrave;", "\\u{ec}", "\\u00ec", "%C3%AC", "\\`{\\i}"]);
+        assert_eq!(formats("ß"), ["U+00DF", "&szlig;", "\\u{df}", "\\u00df", "%C3%9F", "\\ss{}"]);
+        // Astral: a numeric entity, a surrogate pair in JSON, the glyph itself in LaTeX
+        assert_eq!(formats("😀"), ["U+1F600", "&#x1F600;", "\\u{1f600}", "\\ud83d\\ude00", "%F0%9F%98%80", "😀"]);
+        assert_eq!(Representation::Literal.format("😀"), "😀");
+    }
+
+    #[test]
+    fn formats_each_character_of_a_sequence() {
+        assert_eq!(Representation::CodePoint.format("e\u{301}"), "U+0065 U+0301");
+        assert_eq!(Representation::HtmlEntity.format("<é>"), "&lt;&eacute;&gt;");
+        assert_eq!(Representation::Url.format("a b-c"), "a%20b-c");
+    }
+}
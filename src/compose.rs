@@ -1,17 +1,44 @@
+use crate::charinfo::{is_combining, symbol_category, SYMBOL_CATEGORIES};
+use crate::composefile;
 use crate::keyboard::XkbKeymap;
-use std::collections::HashMap;
-
-/// Represents a single compose sequence result
+use crate::keylabel::KeyLabels;
+use std::collections::{BTreeMap, HashMap};
+use unicode_normalization::UnicodeNormalization;
+
+// Blocks searched for characters the layout can't type: Latin, Greek and
+// Cyrillic letters, punctuation, common symbols and emoji
+const UNREACHABLE_RANGES: &[std::ops::RangeInclusive<u32>] = &[
+    0xA0..=0x24F,
+    0x370..=0x4FF,
+    0x1E00..=0x1FFF,
+    0x2010..=0x205E,
+    0x20A0..=0x20C0,
+    0x2100..=0x22FF,
+    0x25A0..=0x27BF,
+    0x1F300..=0x1F6FF,
+    0x1F900..=0x1F9FF,
+];
+
+/// Index key for combining marks (U+0301 etc.), which have no base letter
+pub const COMBINING_MARKS: char = '◌';
+
+/// A character and the key sequences that produce it
 #[derive(Clone, Debug)]
 pub struct ComposeEntry {
     pub character: String,
+    // Shortest route, shown in the result row
     pub key_sequence: String,
+    // Other routes to the same character, shortest first
+    pub alternatives: Vec<String>,
 }
 
 /// Index of base characters to their variants
 pub struct ComposeIndex {
     // Maps base character (e.g., 'e') to all its variants
     index: HashMap<char, Vec<ComposeEntry>>,
+    // Symbols and emoji from Compose sequences, by SYMBOL_CATEGORIES name;
+    // only found through name search
+    symbols: HashMap<&'static str, Vec<ComposeEntry>>,
 }
 
 impl ComposeIndex {
@@ -26,13 +53,29 @@ impl ComposeIndex {
 
         let mut index: HashMap<char, Vec<ComposeEntry>> = HashMap::new();
         let keymap = xkb.keymap();
-
-        // Iterate through all keycodes (8-255 is the standard range)
-        for keycode_raw in 8..256 {
+        let labels = KeyLabels::from_locale();
+        // xkb allows keycodes above 255 (e.g. some media and international keys)
+        let keycodes = keymap.min_keycode().raw()..=keymap.max_keycode().raw();
+
+        // Keys with a plain letter on their base level, so combining marks
+        // and dead keys can be shown as "..., then the letter key"
+        let letter_keys: Vec<(char, String)> = keycodes
+            .clone()
+            .map(xkb::Keycode::new)
+            .filter_map(|keycode| {
+                let &sym = keymap.key_get_syms_by_level(keycode, 0, 0).first()?;
+                let letter = char::from_u32(xkb::keysym_to_utf32(sym))?;
+                (letter.is_alphabetic() && !letter.is_uppercase()).then(|| (letter, labels.label(keymap, keycode)))
+            })
+            .collect();
+
+        let mut symbols: HashMap<&'static str, Vec<ComposeEntry>> = HashMap::new();
+        for keycode_raw in keycodes.clone() {
             let keycode = xkb::Keycode::new(keycode_raw);
 
-            // Skip if no key name
-            if keymap.key_get_name(keycode).is_none() {
+            // Skip if no key name, and the extra <Innn> keys only some
+            // keyboards have (KEY_EURO), which would outrank AltGr-5
+            if keymap.key_get_name(keycode).is_none_or(|name| name.starts_with('I')) {
                 continue;
             }
 
@@ -59,84 +102,76 @@ impl ComposeIndex {
                     continue;
                 }
 
-                let keysym = syms[0];
+                // Levels can emit several keysyms (e.g. ligatures or a letter
+                // plus combining marks); they produce all their characters
+                let Some(text) = syms
+                    .iter()
+                    .map(|&sym| char::from_u32(xkb::keysym_to_utf32(sym)))
+                    .collect::<Option<String>>()
+                else {
+                    continue;
+                };
 
-                // Convert keysym to UTF-32 character
-                let utf32 = xkb::keysym_to_utf32(keysym);
-                if let Some(ch) = char::from_u32(utf32) {
-                    // Skip control characters and whitespace
-                    if ch.is_control() || ch.is_whitespace() {
-                        continue;
-                    }
-
-                    // Determine modifier prefix based on level
-                    let mod_prefix = match level {
-                        0 => {
-                            // Skip basic ASCII letters at level 0 (no modifiers)
-                            if ch.is_ascii_lowercase() {
-                                continue;
-                            }
-                            ""
-                        },
-                        1 => {
-                            // Skip uppercase ASCII at level 1 (Shift)
-                            if ch.is_ascii_uppercase() {
-                                continue;
-                            }
-                            "Shift+"
-                        },
-                        2 => "AltGr+",
-                        3 => {
-                            // Skip if this is just the uppercase of level 2
-                            // (obvious Shift capitalization)
-                            if let Some(l2_char) = level2_char {
-                                if ch == l2_char.to_uppercase().next().unwrap_or(l2_char)
-                                   && ch != l2_char {
-                                    continue;
-                                }
-                            }
-                            "AltGr+Shift+"
-                        },
-                        _ => continue,
-                    };
-
-                    // Get the physical key name (what key to press)
-                    let key_name = keymap.key_get_name(keycode).unwrap_or("?");
-
-                    // Convert XKB key name to something user-friendly
-                    // XKB names are like "AD01" (row A, position D, key 01) or "AC01", etc.
-                    // We need to show the actual character on that key (level 0)
-                    let base_sym = keymap.key_get_syms_by_level(keycode, 0, 0);
-                    let physical_key = if !base_sym.is_empty() {
-                        let base_char = xkb::keysym_to_utf32(base_sym[0]);
-                        if let Some(c) = char::from_u32(base_char) {
-                            if c.is_ascii_graphic() {
-                                c.to_string()
-                            } else {
-                                key_name.to_string()
-                            }
-                        } else {
-                            key_name.to_string()
+                // Skip control characters and whitespace
+                if text.is_empty() || text.chars().any(|c| c.is_control() || c.is_whitespace()) {
+                    continue;
+                }
+                let ch = text.chars().next().unwrap();
+                let single = text.chars().count() == 1;
+
+                // Determine modifier prefix based on level
+                let mod_prefix = match level {
+                    0 => {
+                        // Skip plain letters at level 0 (no modifiers), in any script
+                        if single && is_plain_letter(ch) {
+                            continue;
                         }
-                    } else {
-                        key_name.to_string()
-                    };
+                        ""
+                    },
+                    1 => {
+                        // Skip their uppercase at level 1 (Shift)
+                        if single && ch.is_uppercase() && ch.to_lowercase().next().is_some_and(is_plain_letter) {
+                            continue;
+                        }
+                        "Shift+"
+                    },
+                    2 => "AltGr+",
+                    3 => {
+                        // Skip if this is just the uppercase of level 2
+                        // (obvious Shift capitalization)
+                        if single && level2_char.and_then(simple_uppercase) == Some(ch) {
+                            continue;
+                        }
+                        "AltGr+Shift+"
+                    },
+                    _ => continue,
+                };
+
+                // The physical key to press, labelled by what's printed on it
+                let physical_key = labels.label(keymap, keycode);
 
-                    // Build the key sequence string (use dash for simultaneous keys)
-                    let key_sequence = format!("{}{}", mod_prefix.replace('+', "-"), physical_key);
+                // Build the key sequence string (use dash for simultaneous keys)
+                let key_sequence = format!("{}{}", mod_prefix.replace('+', "-"), physical_key);
 
-                    // Try to find a base character to index this under
-                    if let Some(base) = find_base_char(ch) {
-                        add_entry(&mut index, base, &ch.to_string(), &key_sequence);
-                    }
+                if single && is_combining(ch) {
+                    add_entry(&mut index, COMBINING_MARKS, &text, &key_sequence);
+                    add_letter_routes(&mut index, &letter_keys, ch, &key_sequence);
+                    continue;
+                }
+
+                // ASCII digits and punctuation are printed on the plain keys;
+                // elsewhere (the keypad's AltGr level) they're just noise
+                if text.is_ascii() && !text.chars().any(|c| find_base_char(c).is_some()) {
+                    continue;
                 }
+                add_output(&mut index, &mut symbols, &text, &key_sequence);
             }
         }
 
         // Now scan for dead keys and add their combinations
-        let mut dead_keys: Vec<(String, String)> = Vec::new(); // (physical_key, dead_key_type)
+        let mut dead_keys: Vec<(String, xkb::Keysym)> = Vec::new(); // (physical_key, dead keysym)
 
-        for keycode_raw in 8..256 {
+        for keycode_raw in keycodes.clone() {
             let keycode = xkb::Keycode::new(keycode_raw);
 
             if keymap.key_get_name(keycode).is_none() {
@@ -145,7 +180,8 @@ impl ComposeIndex {
 
             let num_levels = keymap.num_levels_for_key(keycode, 0);
 
-            for level in 2..num_levels.min(4) {  // Only AltGr levels
+            // Greek and Cyrillic layouts put dead keys on the base levels too
+            for level in 0..num_levels.min(4) {
                 let syms = keymap.key_get_syms_by_level(keycode, 0, level);
                 if syms.is_empty() {
                     continue;
@@ -156,102 +192,461 @@ impl ComposeIndex {
 
                 // Check if this is a dead key
                 if keysym_name.starts_with("dead_") {
-                    // Get the physical key
-                    let base_sym = keymap.key_get_syms_by_level(keycode, 0, 0);
-                    let physical_key = if !base_sym.is_empty() {
-                        let base_char = xkb::keysym_to_utf32(base_sym[0]);
-                        if let Some(c) = char::from_u32(base_char) {
-                            if c.is_ascii_graphic() {
-                                c.to_string()
-                            } else {
-                                continue;
-                            }
-                        } else {
-                            continue;
-                        }
-                    } else {
-                        continue;
-                    };
+                    let physical_key = labels.label(keymap, keycode);
 
-                    let mod_prefix = if level == 2 { "AltGr-" } else { "AltGr-Shift-" };
-                    dead_keys.push((format!("{}{}", mod_prefix, physical_key), keysym_name.clone()));
+                    let mod_prefix = ["", "Shift-", "AltGr-", "AltGr-Shift-"][level as usize];
+                    dead_keys.push((format!("{}{}", mod_prefix, physical_key), keysym));
                 }
             }
         }
 
-        // Add dead key combinations (both lowercase and uppercase)
-        for (dead_key_combo, dead_type) in &dead_keys {
-            let combinations = get_dead_key_combinations(dead_type);
-            for (base_letter, result_char) in combinations {
-                // Add lowercase variant
-                let key_sequence = format!("{}  {}", dead_key_combo, base_letter);
-                if let Some(base) = find_base_char(result_char) {
-                    add_entry(&mut index, base, &result_char.to_string(), &key_sequence);
+        // Add dead key combinations (both lowercase and uppercase). The
+        // Compose table knows them for every script; without one, fall back
+        // to the common Latin ones.
+        let compose_table = xkb.compose_table();
+        for (dead_key_combo, dead_keysym) in &dead_keys {
+            let combinations = match &compose_table {
+                Some(table) => composed_combinations(table, *dead_keysym, &letter_keys),
+                None => static_combinations(&xkb::keysym_get_name(*dead_keysym)),
+            };
+            for (second_key, result) in combinations {
+                if result.chars().any(|c| c.is_control() || c.is_whitespace()) {
+                    continue;
                 }
+                let key_sequence = format!("{}  {}", dead_key_combo, second_key);
+                add_output(&mut index, &mut symbols, &result, &key_sequence);
+            }
+        }
 
-                // Add uppercase variant
-                let upper_base = base_letter.to_uppercase().next().unwrap();
-                let upper_result = result_char.to_uppercase().next().unwrap();
-                let upper_key_sequence = format!("{}  {}", dead_key_combo, upper_base);
-                if let Some(base) = find_base_char(upper_result) {
-                    add_entry(&mut index, base, &upper_result.to_string(), &upper_key_sequence);
+        // Compose key sequences, indexed like the outputs above
+        let keysym_keys = keysym_keys(keymap, &labels, keycodes);
+        if keysym_keys.contains_key(&xkb::Keysym::Multi_key) {
+            for rule in composefile::load_rules() {
+                // Dead key rules were handled above
+                if rule.keysyms.first() != Some(&xkb::Keysym::Multi_key) {
+                    continue;
+                }
+                let text = rule.text;
+                if text.is_empty() || text.chars().any(|c| c.is_control() || c.is_whitespace()) {
+                    continue;
                 }
+                // Rules that need keys this layout doesn't have can't be typed
+                let Some(steps) = rule.keysyms.iter().map(|keysym| keysym_keys.get(keysym).cloned()).collect::<Option<Vec<String>>>() else {
+                    continue;
+                };
+                let key_sequence = steps.join("  ");
+                add_output(&mut index, &mut symbols, &text, &key_sequence);
             }
+        } else {
+            eprintln!("No Compose key on this layout, Compose sequences are not indexed");
         }
 
-        eprintln!("Found {} base characters with variants", index.len());
+        eprintln!(
+            "Found {} base characters with variants and {} symbols",
+            index.len(),
+            symbols.values().map(Vec::len).sum::<usize>()
+        );
 
-        Ok(Self { index })
+        Ok(Self { index, symbols })
     }
 
-    /// Find all character variants for a given base character
-    pub fn find_variants(&self, input: &str) -> Vec<ComposeEntry> {
-        // Get the first character from input
-        let base_char = input.chars().next();
+    /// The index as a TOML table, for the on-disk cache
+    pub fn to_table(&self) -> toml::Table {
+        fn entries(entries: &[ComposeEntry]) -> toml::Value {
+            let entries: Vec<toml::Value> = entries
+                .iter()
+                .map(|entry| {
+                    let mut table = toml::Table::new();
+                    table.insert("character".to_string(), entry.character.clone().into());
+                    table.insert("sequence".to_string(), entry.key_sequence.clone().into());
+                    table.insert("alternatives".to_string(), entry.alternatives.clone().into());
+                    table.into()
+                })
+                .collect();
+            entries.into()
+        }
 
-        if let Some(ch) = base_char {
-            let is_upper = ch.is_uppercase();
-            let lookup_key = ch.to_lowercase().next().unwrap();
+        let index: toml::Table = self.index.iter().map(|(base, list)| (base.to_string(), entries(list))).collect();
+        let symbols: toml::Table = self.symbols.iter().map(|(category, list)| (category.to_string(), entries(list))).collect();
+        let mut table = toml::Table::new();
+        table.insert("index".to_string(), index.into());
+        table.insert("symbols".to_string(), symbols.into());
+        table
+    }
 
-            if let Some(variants) = self.index.get(&lookup_key) {
-                // Filter by case: if input is uppercase, only show uppercase variants
-                variants
-                    .iter()
-                    .filter(|entry| {
-                        let first_char = entry.character.chars().next().unwrap_or(' ');
-                        if is_upper {
-                            first_char.is_uppercase()
-                        } else {
-                            first_char.is_lowercase()
-                        }
+    /// Read back a table written by `to_table`; None if anything is off
+    pub fn from_table(table: &toml::Table) -> Option<Self> {
+        fn entries(value: &toml::Value) -> Option<Vec<ComposeEntry>> {
+            value
+                .as_array()?
+                .iter()
+                .map(|entry| {
+                    let entry = entry.as_table()?;
+                    Some(ComposeEntry {
+                        character: entry.get("character")?.as_str()?.to_string(),
+                        key_sequence: entry.get("sequence")?.as_str()?.to_string(),
+                        alternatives: entry
+                            .get("alternatives")?
+                            .as_array()?
+                            .iter()
+                            .map(|route| route.as_str().map(str::to_string))
+                            .collect::<Option<_>>()?,
                     })
-                    .cloned()
-                    .collect()
+                })
+                .collect()
+        }
+
+        let mut index = HashMap::new();
+        for (base, list) in table.get("index")?.as_table()? {
+            let mut chars = base.chars();
+            let (Some(base), None) = (chars.next(), chars.next()) else {
+                return None;
+            };
+            index.insert(base, entries(list)?);
+        }
+        let mut symbols = HashMap::new();
+        for (category, list) in table.get("symbols")?.as_table()? {
+            // Back to the &'static name the lookups use
+            let category = SYMBOL_CATEGORIES.iter().find(|name| *name == category)?;
+            symbols.insert(*category, entries(list)?);
+        }
+        Some(Self { index, symbols })
+    }
+
+    /// The entry for exactly this character, with all its routes
+    pub fn entry_for(&self, character: &str) -> Option<ComposeEntry> {
+        let first = character.chars().next()?;
+        let entries = match find_base_char(first).or_else(|| is_combining(first).then_some(COMBINING_MARKS)) {
+            Some(base) => self.index.get(&base)?,
+            None => self.symbols.get(symbol_category(character))?,
+        };
+        entries.iter().find(|entry| entry.character == character).cloned()
+    }
+
+    /// Find characters by Unicode name ("arrow", "left arrow") or list a
+    /// whole symbol category ("emoji", "math")
+    pub fn search_names(&self, query: &str) -> Vec<ComposeEntry> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return Vec::new();
+        }
+        let words: Vec<String> = query.split_whitespace().map(str::to_uppercase).collect();
+        let matches_name = |entry: &ComposeEntry| {
+            let names: Vec<String> = entry
+                .character
+                .chars()
+                .filter_map(|c| unicode_names2::name(c).map(|name| name.to_string()))
+                .collect();
+            name_matches(&names.join(" "), &words)
+        };
+
+        let mut results = Vec::new();
+        for category in SYMBOL_CATEGORIES {
+            let Some(entries) = self.symbols.get(category) else {
+                continue;
+            };
+            if category.starts_with(&query) {
+                results.extend(entries.iter().cloned());
             } else {
-                Vec::new()
+                results.extend(entries.iter().filter(|entry| matches_name(entry)).cloned());
             }
-        } else {
-            Vec::new()
         }
+        results.extend(self.all_entries().into_iter().filter(|entry| matches_name(entry)));
+        results
+    }
+
+    /// Characters the layout can't type that match a base letter, or with
+    /// `by_name` a name search. Their key sequence is empty.
+    pub fn find_unreachable(&self, input: &str, by_name: bool) -> Vec<ComposeEntry> {
+        let Some(ch) = input.chars().next() else {
+            return Vec::new();
+        };
+        let words: Vec<String> = input.split_whitespace().map(str::to_uppercase).collect();
+        let lookup_key = ch.to_lowercase().next().unwrap();
+
+        UNREACHABLE_RANGES
+            .iter()
+            .flat_map(|range| range.clone().filter_map(char::from_u32))
+            .filter(|&c| {
+                if by_name {
+                    unicode_names2::name(c).is_some_and(|name| name_matches(&name.to_string(), &words))
+                } else {
+                    c.to_lowercase().next() != Some(lookup_key) && find_base_char(c) == Some(lookup_key) && matches_case(&c.to_string(), ch.is_uppercase())
+                }
+            })
+            .map(|c| c.to_string())
+            .filter(|character| !character.chars().any(char::is_control) && self.entry_for(character).is_none())
+            .map(|character| ComposeEntry { character, key_sequence: String::new(), alternatives: Vec::new() })
+            .collect()
+    }
+
+    /// Every route to every character the layout can type, letters and
+    /// symbols alike, shortest first. ASCII is left out: it is on the plain
+    /// keys, which the index skips, and only appears as an odd extra route
+    /// (AltGr-j) or Compose sequence (Compose + + for #).
+    pub fn routes(&self) -> BTreeMap<String, Vec<String>> {
+        self.index
+            .values()
+            .chain(self.symbols.values())
+            .flatten()
+            .filter(|entry| !entry.character.is_ascii())
+            .map(|entry| {
+                let routes = std::iter::once(&entry.key_sequence).chain(&entry.alternatives).cloned().collect();
+                (entry.character.clone(), routes)
+            })
+            .collect()
+    }
+
+    /// An index of (character, key sequence) routes, for tests elsewhere
+    #[cfg(test)]
+    pub fn from_routes(routes: &[(&str, &str)]) -> Self {
+        let (mut index, mut symbols) = (HashMap::new(), HashMap::new());
+        for (character, key_sequence) in routes {
+            add_output(&mut index, &mut symbols, character, key_sequence);
+        }
+        ComposeIndex { index, symbols }
+    }
+
+    /// Every character in the base-letter index, grouped by base letter
+    pub fn all_entries(&self) -> Vec<ComposeEntry> {
+        let mut bases: Vec<&char> = self.index.keys().collect();
+        bases.sort();
+        bases.into_iter().flat_map(|base| self.index[base].iter().cloned()).collect()
+    }
+
+    /// Find all character variants for a given base character. A Latin
+    /// letter also finds the variants of the letters it transliterates
+    /// (a → α, а), when the layout has any.
+    pub fn find_variants(&self, input: &str) -> Vec<ComposeEntry> {
+        // Get the first character from input
+        let Some(ch) = input.chars().next() else {
+            return Vec::new();
+        };
+        let is_upper = ch.is_uppercase();
+        let lookup_key = ch.to_lowercase().next().unwrap();
+
+        let aliases = TRANSLITERATIONS
+            .iter()
+            .find(|(latin, _)| *latin == lookup_key)
+            .map_or("", |(_, bases)| bases);
+
+        std::iter::once(lookup_key)
+            .chain(aliases.chars())
+            .filter_map(|base| self.index.get(&base))
+            .flatten()
+            // Filter by case: if input is uppercase, only show uppercase variants
+            .filter(|entry| matches_case(&entry.character, is_upper))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Whether every (uppercase) query word starts a word of a character name
+fn name_matches(name: &str, words: &[String]) -> bool {
+    words.iter().all(|word| name.split([' ', '-']).any(|part| part.starts_with(word.as_str())))
+}
+
+/// Helper function to add a route to the index. Routes to a character that
+/// is already indexed are grouped into its entry.
+/// Index an output under the base of every character, so a two-letter
+/// output like "ij" shows up for both i and j. Letters join the base-letter
+/// index; symbols and emoji (AltGr-/ → ¿, Compose sequences) get their own
+/// categories for name search.
+fn add_output(
+    index: &mut HashMap<char, Vec<ComposeEntry>>,
+    symbols: &mut HashMap<&'static str, Vec<ComposeEntry>>,
+    text: &str,
+    key_sequence: &str,
+) {
+    let mut bases: Vec<char> = text.chars().filter_map(find_base_char).collect();
+    bases.sort();
+    bases.dedup();
+    if !bases.is_empty() {
+        for base in bases {
+            add_entry(index, base, text, key_sequence);
+        }
+    } else if text.starts_with(is_combining) {
+        add_entry(index, COMBINING_MARKS, text, key_sequence);
+    } else {
+        add_route(symbols.entry(symbol_category(text)).or_default(), text, key_sequence);
     }
 }
 
-/// Helper function to add an entry to the index
 fn add_entry(
     index: &mut HashMap<char, Vec<ComposeEntry>>,
     base: char,
     character: &str,
     key_sequence: &str,
 ) {
-    let entry = ComposeEntry {
-        character: character.to_string(),
-        key_sequence: key_sequence.to_string(),
+    add_route(index.entry(base).or_default(), character, key_sequence);
+}
+
+/// Add a route to a list of entries, grouping it with earlier routes to
+/// the same character
+fn add_route(entries: &mut Vec<ComposeEntry>, character: &str, key_sequence: &str) {
+    let Some(entry) = entries.iter_mut().find(|e| e.character == character) else {
+        entries.push(ComposeEntry {
+            character: character.to_string(),
+            key_sequence: key_sequence.to_string(),
+            alternatives: Vec::new(),
+        });
+        return;
     };
 
-    index
-        .entry(base)
-        .or_insert_with(Vec::new)
-        .push(entry);
+    if entry.key_sequence == key_sequence || entry.alternatives.iter().any(|a| a == key_sequence) {
+        return;
+    }
+    let mut routes = std::mem::take(&mut entry.alternatives);
+    routes.push(std::mem::take(&mut entry.key_sequence));
+    routes.push(key_sequence.to_string());
+    // Fewest steps first, then fewest modifiers
+    routes.sort_by_key(|route| (route.split("  ").count(), route.len(), route.clone()));
+    entry.key_sequence = routes.remove(0);
+    entry.alternatives = routes;
+}
+
+/// Non-Latin base letters a Latin letter can stand for, so users who search
+/// with a Latin layout in mind still find Greek, Cyrillic, Hebrew and Arabic
+/// variants
+const TRANSLITERATIONS: &[(char, &str)] = &[
+    ('a', "αаאا"),
+    ('b', "βбבب"),
+    ('c', "ц"),
+    ('d', "δдדد"),
+    ('e', "εηеэєע"),
+    ('f', "φфف"),
+    ('g', "γгґגغ"),
+    ('h', "хהه"),
+    ('i', "ιиіїי"),
+    ('j', "й"),
+    ('k', "κкכك"),
+    ('l', "λлלل"),
+    ('m', "μмמم"),
+    ('n', "νнנن"),
+    ('o', "οωо"),
+    ('p', "πпפ"),
+    ('q', "ق"),
+    ('r', "ρрרر"),
+    ('s', "σςсשس"),
+    ('t', "τтטתت"),
+    ('u', "υуو"),
+    ('v', "вו"),
+    ('w', "ω"),
+    ('x', "ξχ"),
+    ('y', "υыي"),
+    ('z', "ζзזز"),
+];
+
+/// The key (with modifiers) that types each keysym, preferring the lowest
+/// shift level, e.g. greater → "Shift-."
+fn keysym_keys(
+    keymap: &xkbcommon::xkb::Keymap,
+    labels: &KeyLabels,
+    keycodes: std::ops::RangeInclusive<u32>,
+) -> HashMap<xkbcommon::xkb::Keysym, String> {
+    use xkbcommon::xkb;
+
+    let mut keys = HashMap::new();
+    for (level, mod_prefix) in ["", "Shift-", "AltGr-", "AltGr-Shift-"].into_iter().enumerate() {
+        for keycode in keycodes.clone().map(xkb::Keycode::new) {
+            if let &[keysym] = keymap.key_get_syms_by_level(keycode, 0, level as u32) {
+                keys.entry(keysym).or_insert_with(|| format!("{}{}", mod_prefix, labels.label(keymap, keycode)));
+            }
+        }
+    }
+    keys
+}
+
+/// Routes that type a letter and then a combining key, for the letters
+/// where the pair has a precomposed form (e then ◌́ gives é)
+fn add_letter_routes(
+    index: &mut HashMap<char, Vec<ComposeEntry>>,
+    letter_keys: &[(char, String)],
+    mark: char,
+    mark_sequence: &str,
+) {
+    for (letter, label) in letter_keys {
+        let upper = simple_uppercase(*letter).map(|upper| (upper, format!("Shift-{}", label)));
+        for (letter, key) in std::iter::once((*letter, label.clone())).chain(upper) {
+            let mut composed = [letter, mark].into_iter().nfc();
+            let (Some(c), None) = (composed.next(), composed.next()) else {
+                continue;
+            };
+            if let Some(base) = find_base_char(c) {
+                add_entry(index, base, &c.to_string(), &format!("{}  {}", key, mark_sequence));
+            }
+        }
+    }
+}
+
+/// The uppercase of a character if it is a different single character.
+/// None for caseless characters (€) and for characters whose uppercase is
+/// a multi-character string (ß → SS, ŉ → ʼN, ǰ → J̌).
+fn simple_uppercase(c: char) -> Option<char> {
+    let mut upper = c.to_uppercase();
+    let first = upper.next()?;
+    if upper.next().is_some() || first == c {
+        None
+    } else {
+        Some(first)
+    }
+}
+
+/// Whether a variant belongs in the results for an uppercase or lowercase
+/// query. Caseless symbols (€, £) match both.
+fn matches_case(character: &str, upper: bool) -> bool {
+    let Some(first) = character.chars().next() else {
+        return false;
+    };
+    let caseless = !first.is_uppercase() && !first.is_lowercase();
+    caseless || first.is_uppercase() == upper
+}
+
+/// What a dead key followed by each letter key produces according to the
+/// Compose table, as (second key, result) pairs
+fn composed_combinations(
+    table: &xkbcommon::xkb::compose::Table,
+    dead_keysym: xkbcommon::xkb::Keysym,
+    letter_keys: &[(char, String)],
+) -> Vec<(String, String)> {
+    use xkbcommon::xkb;
+
+    let mut state = xkb::compose::State::new(table, xkb::compose::STATE_NO_FLAGS);
+    let mut combinations = Vec::new();
+    for (letter, label) in letter_keys {
+        // Letter keys are labelled with their letter, so Shift+e reads as "E"
+        let upper = simple_uppercase(*letter).map(|upper| {
+            let key = if *label == letter.to_string() { upper.to_string() } else { format!("Shift-{}", label) };
+            (upper, key)
+        });
+        for (letter, key) in std::iter::once((*letter, label.clone())).chain(upper) {
+            state.reset();
+            state.feed(dead_keysym);
+            state.feed(xkb::utf32_to_keysym(letter as u32));
+            if state.status() != xkb::compose::Status::Composed {
+                continue;
+            }
+            if let Some(result) = state.utf8().filter(|result| !result.is_empty()) {
+                combinations.push((key, result));
+            }
+        }
+    }
+    combinations
+}
+
+/// Common Latin dead key combinations, for when there is no Compose table,
+/// as (second key, result) pairs
+fn static_combinations(dead_type: &str) -> Vec<(String, String)> {
+    let mut combinations = Vec::new();
+    for (base_letter, result_char) in get_dead_key_combinations(dead_type) {
+        combinations.push((base_letter.to_string(), result_char.to_string()));
+        // Add uppercase variant, unless the uppercase isn't a single
+        // precomposed character (e.g. ǰ → J̌) that the dead key could produce
+        if let (Some(upper_base), Some(upper_result)) = (simple_uppercase(base_letter), simple_uppercase(result_char)) {
+            combinations.push((upper_base.to_string(), upper_result.to_string()));
+        }
+    }
+    combinations
 }
 
 /// Get common dead key combinations
@@ -302,31 +697,139 @@ fn get_dead_key_combinations(dead_type: &str) -> Vec<(char, char)> {
     }
 }
 
-/// Find the base character for an accented character
-/// e.g., é → e, ñ → n, ø → o
+/// Find the base character for an accented character, in any script
+/// e.g., é → e, ñ → n, ø → o, ά → α, ё → е
 fn find_base_char(ch: char) -> Option<char> {
-    // Use Unicode NFD decomposition to strip accents
-    use unicode_normalization::UnicodeNormalization;
+    // Letters without a decomposition that still read as a Latin letter,
+    // and symbols named after one
+    let special = match ch.to_lowercase().next()? {
+        '€' => Some('e'),
+        '£' => Some('l'),
+        '¥' => Some('y'),
+        '¢' => Some('c'),
+        'æ' => Some('a'),
+        'œ' => Some('o'),
+        'ø' => Some('o'),
+        'ß' => Some('s'),
+        'ð' | 'đ' => Some('d'),
+        'þ' | 'ŧ' => Some('t'),
+        'ł' => Some('l'),
+        'ħ' => Some('h'),
+        'ı' => Some('i'),
+        _ => None,
+    };
+    if special.is_some() {
+        return special;
+    }
 
-    let decomposed: String = ch.nfd().collect();
-    let base = decomposed.chars().next()?;
+    // Use Unicode NFD decomposition to strip accents
+    let base = ch.nfd().next()?;
 
     // Only return if it's a letter
-    if base.is_alphabetic() && base.is_ascii() {
-        Some(base.to_ascii_lowercase())
+    if base.is_alphabetic() {
+        base.to_lowercase().next()
     } else {
-        // For special characters like €, £, etc., try to map to related letters
-        match ch {
-            '€' => Some('e'),
-            '£' => Some('l'),
-            '¥' => Some('y'),
-            '¢' => Some('c'),
-            'æ' => Some('a'),
-            'œ' => Some('o'),
-            'ß' => Some('s'),
-            'ð' => Some('d'),
-            'þ' => Some('t'),
-            _ => None,
-        }
+        None
+    }
+}
+
+/// A letter without accents, which the layout types without any trick
+fn is_plain_letter(ch: char) -> bool {
+    find_base_char(ch) == Some(ch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn characters(entries: Vec<ComposeEntry>) -> Vec<String> {
+        entries.into_iter().map(|e| e.character).collect()
+    }
+
+    #[test]
+    fn simple_uppercase_maps_single_characters() {
+        assert_eq!(simple_uppercase('é'), Some('É'));
+        assert_eq!(simple_uppercase('ø'), Some('Ø'));
+        assert_eq!(simple_uppercase('ÿ'), Some('Ÿ'));
+    }
+
+    #[test]
+    fn simple_uppercase_rejects_multi_character_mappings() {
+        assert_eq!(simple_uppercase('ß'), None);
+        assert_eq!(simple_uppercase('ŉ'), None);
+        assert_eq!(simple_uppercase('ǰ'), None);
+    }
+
+    #[test]
+    fn simple_uppercase_rejects_caseless_and_uppercase() {
+        assert_eq!(simple_uppercase('€'), None);
+        assert_eq!(simple_uppercase('É'), None);
+    }
+
+    #[test]
+    fn caseless_symbols_match_both_cases() {
+        let index = ComposeIndex::from_routes(&[("é", "AltGr-e"), ("É", "AltGr-Shift-e"), ("€", "AltGr-5")]);
+        assert_eq!(characters(index.find_variants("e")), ["é", "€"]);
+        assert_eq!(characters(index.find_variants("E")), ["É", "€"]);
+    }
+
+    #[test]
+    fn sharp_s_is_lowercase_only() {
+        let index = ComposeIndex::from_routes(&[("ß", "AltGr-s"), ("ẞ", "AltGr-Shift-s")]);
+        assert_eq!(characters(index.find_variants("s")), ["ß"]);
+        assert_eq!(characters(index.find_variants("S")), ["ẞ"]);
+    }
+
+    #[test]
+    fn non_latin_bases_and_transliterations() {
+        let index = ComposeIndex::from_routes(&[("ά", "´  α"), ("Ё", "Shift-ё"), ("é", "AltGr-e"), ("ё", "ё")]);
+        assert_eq!(characters(index.find_variants("α")), ["ά"]);
+        assert_eq!(characters(index.find_variants("a")), ["ά"]);
+        assert_eq!(characters(index.find_variants("e")), ["é", "ё"]);
+        assert_eq!(characters(index.find_variants("Е")), ["Ё"]);
+    }
+
+    #[test]
+    fn duplicate_routes_are_grouped() {
+        let index = ComposeIndex::from_routes(&[("é", "AltGr-'  e"), ("é", "AltGr-e"), ("é", "AltGr-e")]);
+        let variants = index.find_variants("e");
+        assert_eq!(variants.len(), 1);
+        assert_eq!(variants[0].key_sequence, "AltGr-e");
+        assert_eq!(variants[0].alternatives, ["AltGr-'  e"]);
+    }
+
+    #[test]
+    fn symbols_on_keys_are_not_unreachable() {
+        let index = ComposeIndex::from_routes(&[("¿", "AltGr-/"), ("é", "AltGr-e")]);
+        let unreachable = characters(index.find_unreachable("inverted", true));
+        assert!(!unreachable.contains(&"¿".to_string()));
+        assert!(unreachable.contains(&"¡".to_string()));
+        assert!(!characters(index.find_unreachable("e", false)).contains(&"é".to_string()));
+    }
+
+    #[test]
+    fn cache_table_round_trips() {
+        let mut index = ComposeIndex::from_routes(&[("é", "AltGr-'  e"), ("é", "AltGr-e"), ("ß", "AltGr-s")]);
+        add_route(index.symbols.entry("arrows").or_default(), "→", "Multi_key  -  >");
+        let table: toml::Table = index.to_table().to_string().parse().unwrap();
+        let restored = ComposeIndex::from_table(&table).unwrap();
+        assert_eq!(restored.routes(), index.routes());
+        assert_eq!(restored.entry_for("é").unwrap().alternatives, ["AltGr-'  e"]);
+    }
+
+    #[test]
+    fn indexes_symbols_typed_on_layout_levels() {
+        let xkb = XkbKeymap::from_names("us", "altgr-intl", "").unwrap();
+        let index = ComposeIndex::build(&xkb).unwrap();
+        let route = |character: &str| index.entry_for(character).map(|entry| entry.key_sequence);
+        assert_eq!(route("¿").as_deref(), Some("AltGr-/"));
+        assert_eq!(route("×").as_deref(), Some("AltGr-="));
+        assert_eq!(route("¬").as_deref(), Some("AltGr-\\"));
+        assert_eq!(route("°").as_deref(), Some("AltGr-Shift-;"));
+        // Not the KEY_EURO key most keyboards lack
+        assert_eq!(route("€").as_deref(), Some("AltGr-5"));
+        // Plain punctuation stays out
+        assert_eq!(route("/"), None);
+        assert!(characters(index.search_names("inverted question")).contains(&"¿".to_string()));
     }
 }
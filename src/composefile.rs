@@ -0,0 +1,286 @@
+use crate::keyboard::locale;
+use crate::theme::config_home;
+use std::env;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use xkbcommon::xkb;
+
+// Includes nested deeper than this are ignored, like libX11 does
+const MAX_INCLUDE_DEPTH: usize = 5;
+
+/// One rule of a Compose file: the keysyms to press and the text they type
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ComposeRule {
+    pub keysyms: Vec<xkb::Keysym>,
+    pub text: String,
+}
+
+/// Rules of the Compose file xkbcommon loads for this user and locale.
+/// xkbcommon can run a Compose table but not list it, so we read the file
+/// ourselves, following the same lookup order and includes.
+pub fn load_rules() -> Vec<ComposeRule> {
+    let mut rules = Vec::new();
+    let locale = locale();
+    if let Some(path) = user_compose_file().or_else(|| system_compose_file(&locale)) {
+        read_file(&path, &locale, 0, &mut rules);
+    }
+    rules
+}
+
+/// The Compose files `load_rules` reads, includes too, so callers can tell
+/// when any of them changed
+pub fn source_files() -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let locale = locale();
+    if let Some(path) = user_compose_file().or_else(|| system_compose_file(&locale)) {
+        collect_includes(path, &locale, 0, &mut files);
+    }
+    files
+}
+
+fn collect_includes(path: PathBuf, locale: &OsStr, depth: usize, files: &mut Vec<PathBuf>) {
+    let contents = std::fs::read_to_string(&path).unwrap_or_default();
+    files.push(path);
+    if depth >= MAX_INCLUDE_DEPTH {
+        return;
+    }
+    for include in contents.lines().filter_map(|line| line.trim().strip_prefix("include")) {
+        if let Some(path) = parse_string(include.trim()).and_then(|(path, _)| include_path(&path, locale)) {
+            collect_includes(path, locale, depth + 1, files);
+        }
+    }
+}
+
+/// $XCOMPOSEFILE, $XDG_CONFIG_HOME/XCompose or ~/.XCompose, whichever
+/// exists first
+pub fn user_compose_file() -> Option<PathBuf> {
+    let candidates = [
+        env::var_os("XCOMPOSEFILE").filter(|v| !v.is_empty()).map(PathBuf::from),
+        config_home().map(|dir| dir.join("XCompose")),
+        env::var_os("HOME").map(|home| PathBuf::from(home).join(".XCompose")),
+    ];
+    candidates.into_iter().flatten().find(|path| path.exists())
+}
+
+/// The locale's Compose file, looked up in compose.dir
+/// ("en_US.UTF-8/Compose  en_US.UTF-8")
+fn system_compose_file(locale: &OsStr) -> Option<PathBuf> {
+    system_compose_file_in(&locale_dir(), &locale.to_string_lossy())
+}
+
+fn system_compose_file_in(dir: &Path, locale: &str) -> Option<PathBuf> {
+    let compose_dir = std::fs::read_to_string(dir.join("compose.dir")).ok()?;
+    let wanted = resolve_locale(dir, locale);
+    lookup(&compose_dir, |(_, name)| name == wanted).map(|(file, _)| dir.join(file))
+}
+
+/// The name xkbcommon looks up in compose.dir: the C locale gets the
+/// en_US rules, anything else goes through locale.alias ("de_DE" is
+/// "de_DE.ISO8859-1", "en_US.utf8" is "en_US.UTF-8")
+fn resolve_locale(dir: &Path, locale: &str) -> String {
+    if locale == "C" || locale == "POSIX" {
+        return "en_US.UTF-8".to_string();
+    }
+    std::fs::read_to_string(dir.join("locale.alias"))
+        .ok()
+        .and_then(|aliases| lookup(&aliases, |(alias, _)| alias == locale).map(|(_, name)| name.to_string()))
+        .unwrap_or_else(|| locale.to_string())
+}
+
+/// The first "left right" line of an X locale table that matches; the
+/// left name may end in ':'
+fn lookup(contents: &str, matches: impl Fn((&str, &str)) -> bool) -> Option<(&str, &str)> {
+    contents
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            Some((fields.next()?.trim_end_matches(':'), fields.next()?))
+        })
+        .find(|&fields| matches(fields))
+}
+
+/// $XLOCALEDIR, or where libX11 installs its locale data
+fn locale_dir() -> PathBuf {
+    env::var_os("XLOCALEDIR")
+        .filter(|v| !v.is_empty())
+        .map_or_else(|| PathBuf::from("/usr/share/X11/locale"), PathBuf::from)
+}
+
+fn read_file(path: &Path, locale: &OsStr, depth: usize, rules: &mut Vec<ComposeRule>) {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => parse(&contents, locale, depth, rules),
+        Err(e) => eprintln!("Failed to read {}: {}", path.display(), e),
+    }
+}
+
+/// Parse Compose file contents, appending the rules and those of included
+/// files. Lines that use modifiers ("Ctrl <a>") are skipped.
+fn parse(contents: &str, locale: &OsStr, depth: usize, rules: &mut Vec<ComposeRule>) {
+    for line in contents.lines() {
+        let line = line.trim();
+        if let Some(include) = line.strip_prefix("include") {
+            if depth < MAX_INCLUDE_DEPTH {
+                if let Some(path) = parse_string(include.trim()).and_then(|(path, _)| include_path(&path, locale)) {
+                    read_file(&path, locale, depth + 1, rules);
+                }
+            }
+            continue;
+        }
+        if !line.starts_with('<') {
+            continue;
+        }
+        if let Some(rule) = parse_rule(line) {
+            rules.push(rule);
+        }
+    }
+}
+
+/// `<Multi_key> <minus> <greater> : "→" U2192 # RIGHTWARDS ARROW`
+fn parse_rule(line: &str) -> Option<ComposeRule> {
+    let (sequence, result) = line.split_once(':')?;
+
+    let mut keysyms = Vec::new();
+    for name in sequence.split_whitespace() {
+        let name = name.strip_prefix('<')?.strip_suffix('>')?;
+        let keysym = xkb::keysym_from_name(name, xkb::KEYSYM_NO_FLAGS);
+        if keysym == xkb::Keysym::NoSymbol {
+            return None;
+        }
+        keysyms.push(keysym);
+    }
+    if keysyms.is_empty() {
+        return None;
+    }
+
+    // The result is a string, a keysym, or both; the string wins
+    let result = result.trim();
+    let text = match parse_string(result) {
+        Some((text, _)) => text,
+        None => {
+            let name = result.split_whitespace().next()?;
+            let keysym = xkb::keysym_from_name(name, xkb::KEYSYM_NO_FLAGS);
+            char::from_u32(xkb::keysym_to_utf32(keysym)).filter(|&c| c != '\0')?.to_string()
+        }
+    };
+    Some(ComposeRule { keysyms, text })
+}
+
+/// A double-quoted string with \" \\ and octal / hex escapes, plus the rest
+/// of the line after it
+fn parse_string(input: &str) -> Option<(String, &str)> {
+    let mut chars = input.strip_prefix('"')?.char_indices();
+    let mut text = String::new();
+    let mut bytes = Vec::new();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => {
+                text.push_str(&String::from_utf8_lossy(&bytes));
+                return Some((text, &input[i + 2..]));
+            }
+            '\\' => {
+                let (_, escaped) = chars.next()?;
+                let radix = match escaped {
+                    'x' | 'X' => 16,
+                    '0'..='7' => 8,
+                    other => {
+                        text.push_str(&String::from_utf8_lossy(&std::mem::take(&mut bytes)));
+                        text.push(other);
+                        continue;
+                    }
+                };
+                // Escapes are bytes, which together form UTF-8
+                let mut digits = String::new();
+                if radix == 8 {
+                    digits.push(escaped);
+                }
+                let max_digits = if radix == 8 { 3 } else { 2 };
+                while digits.len() < max_digits {
+                    match chars.clone().next() {
+                        Some((_, d)) if d.is_digit(radix) => {
+                            digits.push(d);
+                            chars.next();
+                        }
+                        _ => break,
+                    }
+                }
+                bytes.push(u8::from_str_radix(&digits, radix).ok()?);
+            }
+            other => {
+                text.push_str(&String::from_utf8_lossy(&std::mem::take(&mut bytes)));
+                text.push(other);
+            }
+        }
+    }
+    None
+}
+
+/// Expand %L (the locale's Compose file), %H ($HOME) and %S (the system
+/// locale directory) in an include path
+fn include_path(path: &str, locale: &OsStr) -> Option<PathBuf> {
+    if path == "%L" {
+        return system_compose_file(locale);
+    }
+    let home = env::var("HOME").unwrap_or_default();
+    let expanded = path
+        .replace("%H", &home)
+        .replace("%S", &locale_dir().to_string_lossy());
+    Some(PathBuf::from(expanded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules_of(contents: &str) -> Vec<ComposeRule> {
+        let mut rules = Vec::new();
+        parse(contents, OsStr::new("C"), MAX_INCLUDE_DEPTH, &mut rules);
+        rules
+    }
+
+    #[test]
+    fn parses_strings_and_keysyms() {
+        let rules = rules_of(
+            "# comment\n\
+             <Multi_key> <minus> <greater>\t: \"→\"\tU2192 # RIGHTWARDS ARROW\n\
+             <Multi_key> <o> <c> : copyright\n\
+             <Multi_key> <p> <o> <o> : \"\\360\\237\\222\\251\"\n\
+             <Multi_key> <q> <q> : \"\\\"\"\n",
+        );
+        let texts: Vec<&str> = rules.iter().map(|rule| rule.text.as_str()).collect();
+        assert_eq!(texts, ["→", "©", "💩", "\""]);
+        assert_eq!(rules[0].keysyms, [xkb::Keysym::Multi_key, xkb::Keysym::minus, xkb::Keysym::greater]);
+    }
+
+    #[test]
+    fn skips_modifiers_and_unknown_keysyms() {
+        let rules = rules_of("Ctrl <Multi_key> <a> : \"x\"\n<Multi_key> <not_a_keysym> : \"y\"\n");
+        assert!(rules.is_empty());
+    }
+
+    #[test]
+    fn resolves_the_locale_through_locale_alias() {
+        let dir = env::temp_dir().join(format!("kbdviz-locale-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("locale.alias"),
+            "# comment\nC.UTF-8\t\t\ten_US.UTF-8\nde_DE\t\t\tde_DE.ISO8859-1\nen_US.utf8:\t\t\ten_US.UTF-8\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("compose.dir"),
+            "iso8859-1/Compose\t\tde_DE.ISO8859-1\nen_US.UTF-8/Compose:\t\ten_US.UTF-8\n",
+        )
+        .unwrap();
+
+        let file = |locale: &str| system_compose_file_in(&dir, locale).map(|path| path.strip_prefix(&dir).unwrap().to_path_buf());
+        assert_eq!(file("de_DE"), Some(PathBuf::from("iso8859-1/Compose")));
+        assert_eq!(file("en_US.utf8"), Some(PathBuf::from("en_US.UTF-8/Compose")));
+        assert_eq!(file("C.UTF-8"), Some(PathBuf::from("en_US.UTF-8/Compose")));
+        assert_eq!(file("POSIX"), Some(PathBuf::from("en_US.UTF-8/Compose")));
+        // Already the canonical name
+        assert_eq!(file("de_DE.ISO8859-1"), Some(PathBuf::from("iso8859-1/Compose")));
+        assert_eq!(file("xx_XX"), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,511 @@
+use crate::charinfo::Representation;
+use crate::diff::KeymapSource;
+use crate::rank;
+use crate::theme::{self, Theme};
+use crate::ui::Appearance;
+use smithay_client_toolkit::shell::wlr_layer::Anchor;
+use std::env;
+use std::path::{Path, PathBuf};
+use tiny_skia::ColorU8;
+use xkbcommon::xkb;
+
+/// Actions that can be bound to keys in the `[keys]` section
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Close,
+    Clear,
+    SelectNext,
+    SelectPrev,
+    // Copy in the configured default format
+    Copy,
+    CopyAs(Representation),
+    ToggleFavorite,
+    Forget,
+    Drill,
+    Decode,
+    AddComposeRule,
+    SuggestLayouts,
+}
+
+impl Action {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "close" => Some(Action::Close),
+            "clear" => Some(Action::Clear),
+            "select_next" => Some(Action::SelectNext),
+            "select_prev" => Some(Action::SelectPrev),
+            "copy" => Some(Action::Copy),
+            "toggle_favorite" => Some(Action::ToggleFavorite),
+            "forget" => Some(Action::Forget),
+            "drill" => Some(Action::Drill),
+            "decode" => Some(Action::Decode),
+            "add_compose_rule" => Some(Action::AddComposeRule),
+            "suggest_layouts" => Some(Action::SuggestLayouts),
+            // copy_literal, copy_codepoint, copy_html, ...
+            _ => name
+                .strip_prefix("copy_")
+                .and_then(Representation::from_name)
+                .map(Action::CopyAs),
+        }
+    }
+}
+
+/// `kbdviz diff FROM [TO]`: compare two keymaps instead of searching
+#[derive(Clone)]
+pub struct DiffArgs {
+    pub from: KeymapSource,
+    pub to: KeymapSource,
+    // Print the table and exit, without the overlay
+    pub print_only: bool,
+}
+
+/// Settings from built-in defaults, then the config file, then CLI flags
+#[derive(Clone)]
+pub struct Config {
+    pub anchor: Anchor,
+    pub margin: u32,
+    pub initial_char: Option<char>,
+    // Fixed window size; None means size to fit the content
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub appearance: Appearance,
+    // "auto" follows the desktop color scheme
+    theme_name: String,
+    // [colors] overrides, applied on top of the selected theme
+    color_overrides: Vec<(String, ColorU8)>,
+    // Clipboard command; the text to copy is passed as the last argument
+    pub copy_command: Vec<String>,
+    pub close_on_copy: bool,
+    // Format for plain clicks and the copy action, and for modifier-clicks
+    pub copy_format: Representation,
+    pub shift_click_format: Representation,
+    pub ctrl_click_format: Representation,
+    pub alt_click_format: Representation,
+    pub keybindings: Vec<(xkb::Keysym, Action)>,
+    // Languages whose accents rank first; None means use the locale
+    pub languages: Option<Vec<String>>,
+    pub diff: Option<DiffArgs>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            anchor: Anchor::empty(), // centered by default
+            margin: 0,
+            initial_char: None,
+            width: None,
+            height: None,
+            appearance: Appearance::default(),
+            theme_name: "auto".to_string(),
+            color_overrides: Vec::new(),
+            copy_command: vec!["wl-copy".to_string()],
+            close_on_copy: false,
+            copy_format: Representation::Literal,
+            shift_click_format: Representation::CodePoint,
+            ctrl_click_format: Representation::HtmlEntity,
+            alt_click_format: Representation::Latex,
+            keybindings: vec![
+                (xkb::Keysym::Escape, Action::Close),
+                (xkb::Keysym::BackSpace, Action::Clear),
+                (xkb::Keysym::Down, Action::SelectNext),
+                (xkb::Keysym::Up, Action::SelectPrev),
+                (xkb::Keysym::Return, Action::Copy),
+                (xkb::Keysym::_1, Action::CopyAs(Representation::CodePoint)),
+                (xkb::Keysym::_2, Action::CopyAs(Representation::HtmlEntity)),
+                (xkb::Keysym::_3, Action::CopyAs(Representation::RustEscape)),
+                (xkb::Keysym::_4, Action::CopyAs(Representation::Url)),
+                (xkb::Keysym::_5, Action::CopyAs(Representation::Latex)),
+                (xkb::Keysym::_6, Action::CopyAs(Representation::JsonEscape)),
+                (xkb::Keysym::asterisk, Action::ToggleFavorite),
+                (xkb::Keysym::Delete, Action::Forget),
+                (xkb::Keysym::F2, Action::Drill),
+                (xkb::Keysym::F3, Action::Decode),
+                (xkb::Keysym::F5, Action::AddComposeRule),
+                (xkb::Keysym::F6, Action::SuggestLayouts),
+            ],
+            languages: None,
+            diff: None,
+        }
+    }
+}
+
+impl Config {
+    /// Load the config file (if any) and apply command line flags on top
+    pub fn load() -> Result<Self, String> {
+        let args: Vec<String> = env::args().collect();
+        let mut config = Config::default();
+
+        // --config has to be known before the file is read. Only the
+        // default file may be missing.
+        if let Some(pos) = args.iter().position(|a| a == "--config") {
+            let path = args.get(pos + 1).ok_or("--config requires a value")?;
+            config.apply_file(Path::new(path))?;
+        } else if let Some(path) = default_config_path().filter(|path| path.exists()) {
+            config.apply_file(&path)?;
+        }
+
+        // KBDVIZ_THEME overrides the config file; --theme overrides both
+        if let Ok(name) = env::var("KBDVIZ_THEME") {
+            config.theme_name = name;
+        }
+
+        config.apply_args(&args)?;
+        let mut theme = Theme::load(&config.theme_name)?;
+        for (name, color) in &config.color_overrides {
+            theme.set_color(name, *color);
+        }
+        eprintln!("Using theme: {}", theme.name);
+        config.appearance.theme = theme;
+
+        if config.languages.is_none() {
+            config.languages = Some(rank::locale_languages());
+        }
+
+        Ok(config)
+    }
+
+    /// Name of the first key bound to an action, for on-screen hints
+    pub fn key_name(&self, action: Action) -> Option<String> {
+        self.keybindings
+            .iter()
+            .find(|(_, bound)| *bound == action)
+            .map(|(keysym, _)| {
+                // "*" reads better than "asterisk"
+                match char::from_u32(xkb::keysym_to_utf32(*keysym)) {
+                    Some(c) if c.is_ascii_graphic() => c.to_string(),
+                    _ => xkb::keysym_get_name(*keysym),
+                }
+            })
+    }
+
+    /// Look up the action bound to a keysym
+    pub fn action_for(&self, keysym: xkb::Keysym) -> Option<Action> {
+        self.keybindings
+            .iter()
+            .find(|(bound, _)| *bound == keysym)
+            .map(|(_, action)| *action)
+    }
+
+    fn apply_file(&mut self, path: &Path) -> Result<(), String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        let table: toml::Table = contents
+            .parse()
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        self.apply_table(&table)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn apply_table(&mut self, table: &toml::Table) -> Result<(), String> {
+        for (section, value) in table {
+            let entries = value
+                .as_table()
+                .ok_or_else(|| format!("{}: expected a table", section))?;
+
+            for (name, value) in entries {
+                let key = format!("{}.{}", section, name);
+                match (section.as_str(), name.as_str()) {
+                    ("window", "anchor") => self.anchor = parse_anchor(expect_str(&key, value)?).map_err(|e| format!("{}: {}", key, e))?,
+                    ("window", "margin") => self.margin = expect_u32(&key, value)?,
+                    ("window", "width") => self.width = Some(expect_u32(&key, value)?),
+                    ("window", "height") => self.height = Some(expect_u32(&key, value)?),
+                    ("font", "char_size") => self.appearance.char_font_size = expect_font_size(&key, value)?,
+                    ("font", "key_size") => self.appearance.key_font_size = expect_font_size(&key, value)?,
+                    ("font", "ui_family") => self.appearance.ui_font = Some(expect_str(&key, value)?.to_string()),
+                    ("font", "char_family") => self.appearance.char_font = Some(expect_str(&key, value)?.to_string()),
+                    ("font", "emoji_family") => self.appearance.emoji_font = Some(expect_str(&key, value)?.to_string()),
+                    ("theme", "name") => self.theme_name = expect_str(&key, value)?.to_string(),
+                    ("colors", color) => {
+                        let parsed = theme::parse_color(expect_str(&key, value)?).map_err(|e| format!("{}: {}", key, e))?;
+                        // Validate the name now, apply once the theme is known
+                        Theme::dark()
+                            .set_color(color, parsed)
+                            .ok_or_else(|| format!("{}: unknown color name", key))?;
+                        self.color_overrides.push((color.to_string(), parsed));
+                    }
+                    ("copy", "command") => self.copy_command = expect_command(&key, value)?,
+                    ("copy", "close_after_copy") => {
+                        self.close_on_copy = value.as_bool().ok_or_else(|| format!("{}: expected true or false", key))?;
+                    }
+                    ("copy", "format") => self.copy_format = expect_format(&key, value)?,
+                    ("copy", "shift_click") => self.shift_click_format = expect_format(&key, value)?,
+                    ("copy", "ctrl_click") => self.ctrl_click_format = expect_format(&key, value)?,
+                    ("copy", "alt_click") => self.alt_click_format = expect_format(&key, value)?,
+                    ("search", "languages") => {
+                        let codes = match value {
+                            toml::Value::Array(items) => items
+                                .iter()
+                                .map(|item| item.as_str())
+                                .collect::<Option<Vec<_>>>()
+                                .ok_or_else(|| format!("{}: expected an array of strings", key))?,
+                            _ => return Err(format!("{}: expected an array of strings", key)),
+                        };
+                        self.languages = Some(parse_languages(&codes).map_err(|e| format!("{}: {}", key, e))?);
+                    }
+                    ("keys", action) => {
+                        let action = Action::from_name(action).ok_or_else(|| format!("{}: unknown action", key))?;
+                        let keysyms = expect_keysyms(&key, value)?;
+                        // Replace the default bindings for this action
+                        self.keybindings.retain(|(_, bound)| *bound != action);
+                        self.keybindings.extend(keysyms.into_iter().map(|k| (k, action)));
+                    }
+                    ("window" | "font" | "copy" | "theme" | "search", _) => return Err(format!("{}: unknown key", key)),
+                    _ => return Err(format!("{}: unknown section", section)),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn apply_args(&mut self, args: &[String]) -> Result<(), String> {
+        // Keymaps named after `diff`, and whether to only print the table
+        let mut diff_specs: Option<Vec<&str>> = None;
+        let mut print_only = false;
+
+        let mut i = 1;
+        while i < args.len() {
+            match args[i].as_str() {
+                "--help" | "-h" => {
+                    eprintln!("Usage: kbdviz [OPTIONS]");
+                    eprintln!("       kbdviz diff [--print] FROM [TO]");
+                    eprintln!();
+                    eprintln!("Compare what two keymaps can type. A keymap is \"current\" (the");
+                    eprintln!("default for TO), a keymap file, or a layout like us(intl) with");
+                    eprintln!("optional XKB options: us(intl),compose:ralt");
+                    eprintln!();
+                    eprintln!("Options:");
+                    eprintln!("  --anchor <POS>     Position: top-left, top-right, bottom-left,");
+                    eprintln!("                     bottom-right, top, bottom, left, right, center");
+                    eprintln!("  --margin <PX>      Margin from screen edges (default: 0)");
+                    eprintln!("  --char <LETTER>    Show variants for this character on startup");
+                    eprintln!("  --theme <NAME>     auto, dark, light, high-contrast, or a user theme");
+                    eprintln!("  --lang <CODES>     Languages to rank first, e.g. nl,fr (default: from locale)");
+                    eprintln!("  --config <PATH>    Config file (default: $XDG_CONFIG_HOME/kbdviz/config.toml)");
+                    eprintln!("  --print            With diff: print the table and exit");
+                    eprintln!("  -h, --help         Show this help");
+                    std::process::exit(0);
+                }
+                "--anchor" => {
+                    i += 1;
+                    if i >= args.len() {
+                        return Err("--anchor requires a value".to_string());
+                    }
+                    self.anchor = parse_anchor(&args[i])?;
+                }
+                "--margin" => {
+                    i += 1;
+                    if i >= args.len() {
+                        return Err("--margin requires a value".to_string());
+                    }
+                    self.margin = args[i].parse().map_err(|_| "Invalid margin value")?;
+                }
+                "--char" => {
+                    i += 1;
+                    if i >= args.len() {
+                        return Err("--char requires a value".to_string());
+                    }
+                    let c = args[i].chars().next().ok_or("--char requires a character")?;
+                    if !c.is_alphabetic() {
+                        return Err("--char must be a letter".to_string());
+                    }
+                    self.initial_char = Some(c);
+                }
+                "--theme" => {
+                    i += 1;
+                    if i >= args.len() {
+                        return Err("--theme requires a value".to_string());
+                    }
+                    self.theme_name = args[i].clone();
+                }
+                "--lang" => {
+                    i += 1;
+                    if i >= args.len() {
+                        return Err("--lang requires a value".to_string());
+                    }
+                    let codes: Vec<&str> = args[i].split(',').filter(|c| !c.is_empty()).collect();
+                    self.languages = Some(parse_languages(&codes).map_err(|e| format!("--lang: {}", e))?);
+                }
+                "--config" => {
+                    // Already handled in load()
+                    i += 1;
+                }
+                "diff" if i == 1 => diff_specs = Some(Vec::new()),
+                "--print" => print_only = true,
+                arg => match diff_specs.as_mut() {
+                    Some(specs) if !arg.starts_with('-') => specs.push(arg),
+                    _ => return Err(format!("Unknown argument: {}", arg)),
+                },
+            }
+            i += 1;
+        }
+
+        match diff_specs.as_deref() {
+            Some([from]) => self.diff = Some(DiffArgs { from: KeymapSource::parse(from)?, to: KeymapSource::Current, print_only }),
+            Some([from, to]) => {
+                self.diff = Some(DiffArgs { from: KeymapSource::parse(from)?, to: KeymapSource::parse(to)?, print_only });
+            }
+            Some(_) => return Err("diff takes one or two keymaps".to_string()),
+            None if print_only => return Err("--print only works with diff".to_string()),
+            None => {}
+        }
+        // Without the overlay there is no compositor to ask for the keymap
+        if let Some(diff) = &self.diff {
+            if diff.print_only && [&diff.from, &diff.to].contains(&&KeymapSource::Current) {
+                return Err("diff --print needs two named keymaps; the current one comes from the compositor".to_string());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// $XDG_CONFIG_HOME/kbdviz/config.toml
+fn default_config_path() -> Option<PathBuf> {
+    theme::config_home().map(|dir| dir.join("kbdviz").join("config.toml"))
+}
+
+fn parse_anchor(value: &str) -> Result<Anchor, String> {
+    match value {
+        "top-left" => Ok(Anchor::TOP | Anchor::LEFT),
+        "top-right" => Ok(Anchor::TOP | Anchor::RIGHT),
+        "bottom-left" => Ok(Anchor::BOTTOM | Anchor::LEFT),
+        "bottom-right" => Ok(Anchor::BOTTOM | Anchor::RIGHT),
+        "top" => Ok(Anchor::TOP),
+        "bottom" => Ok(Anchor::BOTTOM),
+        "left" => Ok(Anchor::LEFT),
+        "right" => Ok(Anchor::RIGHT),
+        "center" => Ok(Anchor::empty()),
+        other => Err(format!("Unknown anchor: {}", other)),
+    }
+}
+
+fn expect_str<'a>(key: &str, value: &'a toml::Value) -> Result<&'a str, String> {
+    value.as_str().ok_or_else(|| format!("{}: expected a string", key))
+}
+
+fn expect_u32(key: &str, value: &toml::Value) -> Result<u32, String> {
+    value
+        .as_integer()
+        .and_then(|v| u32::try_from(v).ok())
+        .ok_or_else(|| format!("{}: expected a non-negative integer", key))
+}
+
+fn expect_font_size(key: &str, value: &toml::Value) -> Result<f32, String> {
+    let size = value
+        .as_float()
+        .or_else(|| value.as_integer().map(|v| v as f64))
+        .ok_or_else(|| format!("{}: expected a number", key))?;
+    if !(4.0..=200.0).contains(&size) {
+        return Err(format!("{}: font size must be between 4 and 200", key));
+    }
+    Ok(size as f32)
+}
+
+fn parse_languages(codes: &[&str]) -> Result<Vec<String>, String> {
+    codes
+        .iter()
+        .map(|&code| {
+            if rank::is_known_language(code) {
+                Ok(code.to_string())
+            } else {
+                Err(format!("unknown language \"{}\" (known: {})", code, rank::known_languages().join(", ")))
+            }
+        })
+        .collect()
+}
+
+fn expect_format(key: &str, value: &toml::Value) -> Result<Representation, String> {
+    Representation::from_name(expect_str(key, value)?)
+        .ok_or_else(|| format!("{}: expected one of literal, codepoint, html, escape, json, url, latex", key))
+}
+
+/// A command as a string ("wl-copy -n") or an array (["wl-copy", "-n"])
+fn expect_command(key: &str, value: &toml::Value) -> Result<Vec<String>, String> {
+    let command: Vec<String> = match value {
+        toml::Value::String(s) => s.split_whitespace().map(String::from).collect(),
+        toml::Value::Array(items) => items
+            .iter()
+            .map(|item| item.as_str().map(String::from))
+            .collect::<Option<_>>()
+            .ok_or_else(|| format!("{}: expected an array of strings", key))?,
+        _ => return Err(format!("{}: expected a string or an array of strings", key)),
+    };
+    if command.is_empty() {
+        return Err(format!("{}: command is empty", key));
+    }
+    Ok(command)
+}
+
+/// A single XKB keysym name ("Escape") or an array of them
+fn expect_keysyms(key: &str, value: &toml::Value) -> Result<Vec<xkb::Keysym>, String> {
+    let names: Vec<&str> = match value {
+        toml::Value::String(s) => vec![s.as_str()],
+        toml::Value::Array(items) => items
+            .iter()
+            .map(|item| item.as_str())
+            .collect::<Option<_>>()
+            .ok_or_else(|| format!("{}: expected an array of key names", key))?,
+        _ => return Err(format!("{}: expected a key name or an array of key names", key)),
+    };
+
+    names
+        .into_iter()
+        .map(|name| {
+            let keysym = xkb::keysym_from_name(name, xkb::KEYSYM_NO_FLAGS);
+            if keysym == xkb::Keysym::NoSymbol {
+                Err(format!("{}: unknown key name \"{}\"", key, name))
+            } else {
+                Ok(keysym)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(toml: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        config.apply_table(&toml.parse().unwrap())?;
+        Ok(config)
+    }
+
+    #[test]
+    fn applies_valid_settings() {
+        let config = apply(
+            "[window]\nanchor = \"top-right\"\nmargin = 12\n\
+             [font]\nchar_size = 32\n\
+             [colors]\naccent = \"#ff8800\"\n\
+             [keys]\ncopy = [\"Return\", \"KP_Enter\"]\n",
+        )
+        .unwrap();
+        assert_eq!(config.anchor, Anchor::TOP | Anchor::RIGHT);
+        assert_eq!(config.margin, 12);
+        assert_eq!(config.appearance.char_font_size, 32.0);
+        assert_eq!(config.color_overrides, [("accent".to_string(), ColorU8::from_rgba(0xff, 0x88, 0x00, 0xff))]);
+        assert_eq!(config.action_for(xkb::Keysym::KP_Enter), Some(Action::Copy));
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        let error = |toml: &str| apply(toml).err().unwrap();
+        assert_eq!(error("[windows]\nmargin = 1"), "windows: unknown section");
+        assert_eq!(error("[window]\nmargins = 1"), "window.margins: unknown key");
+        assert_eq!(error("[keys]\ncopy_everything = \"c\""), "keys.copy_everything: unknown action");
+        assert_eq!(error("[colors]\naccent = \"#ff88\""), "colors.accent: expected a color like \"#a6d9ff\", got \"#ff88\"");
+        assert_eq!(error("[colors]\nsparkle = \"#ff8800\""), "colors.sparkle: unknown color name");
+        assert_eq!(error("[keys]\ncopy = \"Retrun\""), "keys.copy: unknown key name \"Retrun\"");
+        assert_eq!(error("[font]\nkey_size = 2"), "font.key_size: font size must be between 4 and 200");
+        assert_eq!(error("[font]\nchar_size = 500.0"), "font.char_size: font size must be between 4 and 200");
+    }
+
+    #[test]
+    fn explicit_config_file_must_exist() {
+        let mut config = Config::default();
+        let error = config.apply_file(Path::new("/nonexistent/kbdviz.toml")).unwrap_err();
+        assert!(error.starts_with("/nonexistent/kbdviz.toml: "), "{}", error);
+    }
+}
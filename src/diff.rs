@@ -0,0 +1,387 @@
+use crate::charinfo::{display_text, is_combining};
+use crate::cache;
+use crate::compose::ComposeIndex;
+use crate::keyboard::XkbKeymap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Where a keymap to compare comes from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeymapSource {
+    /// The keymap the compositor sends us
+    Current,
+    /// A keymap file, as written by `xkbcli compile-keymap`
+    File(PathBuf),
+    /// RMLVO names: "us(intl)", several layouts "us,de(nodeadkeys)", with
+    /// options "us(intl),compose:ralt"; layout and variant are the
+    /// comma-separated lists xkbcommon takes
+    Names { layout: String, variant: String, options: String },
+}
+
+impl KeymapSource {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        if spec == "current" {
+            return Ok(KeymapSource::Current);
+        }
+        if spec.contains('/') || Path::new(spec).is_file() {
+            return Ok(KeymapSource::File(PathBuf::from(spec)));
+        }
+
+        // Options always have a group, "compose:ralt"; layouts never do
+        let (options, names): (Vec<&str>, Vec<&str>) = spec.split(',').partition(|segment| segment.contains(':'));
+        let mut layouts = Vec::new();
+        let mut variants = Vec::new();
+        for name in names {
+            let (layout, variant) = match name.split_once('(') {
+                Some((layout, rest)) => {
+                    let variant = rest.strip_suffix(')').ok_or_else(|| format!("{}: expected layout(variant)", spec))?;
+                    (layout, variant)
+                }
+                None => (name, ""),
+            };
+            if layout.is_empty() || !layout.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                return Err(format!("{}: not a layout name, keymap file or \"current\"", spec));
+            }
+            layouts.push(layout);
+            variants.push(variant);
+        }
+        if layouts.is_empty() {
+            return Err(format!("{}: no layout", spec));
+        }
+        // "us,de" rather than "us,de" with variants ","
+        let variant = if variants.iter().all(|variant| variant.is_empty()) { String::new() } else { variants.join(",") };
+        Ok(KeymapSource::Names { layout: layouts.join(","), variant, options: options.join(",") })
+    }
+
+    /// Compile the keymap; the current one has to come from the compositor
+    pub fn load(&self) -> Result<XkbKeymap, String> {
+        match self {
+            KeymapSource::Current => Err("The current keymap comes from the compositor".to_string()),
+            KeymapSource::File(path) => {
+                let contents = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                XkbKeymap::from_string(&contents).map_err(|e| format!("{}: {}", path.display(), e))
+            }
+            KeymapSource::Names { layout, variant, options } => XkbKeymap::from_names(layout, variant, options),
+        }
+    }
+}
+
+impl fmt::Display for KeymapSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeymapSource::Current => write!(f, "current"),
+            KeymapSource::File(path) => write!(f, "{}", path.display()),
+            KeymapSource::Names { layout, variant, options } => {
+                let mut variants = variant.split(',');
+                for (i, layout) in layout.split(',').enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", layout)?;
+                    match variants.next() {
+                        Some(variant) if !variant.is_empty() => write!(f, "({})", variant)?,
+                        _ => {}
+                    }
+                }
+                if !options.is_empty() {
+                    write!(f, ",{}", options)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ChangeKind {
+    Gained,
+    Lost,
+    Changed,
+}
+
+impl ChangeKind {
+    /// Marker in the first column: + gained, - lost, ~ changed
+    pub fn marker(self) -> &'static str {
+        match self {
+            ChangeKind::Gained => "+",
+            ChangeKind::Lost => "-",
+            ChangeKind::Changed => "~",
+        }
+    }
+}
+
+/// A character whose routes differ between two keymaps, shortest first.
+/// Routes are empty on the side that can't type it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Change {
+    pub kind: ChangeKind,
+    pub character: String,
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+}
+
+/// What moving from one keymap to another does to the characters you can type
+#[derive(Clone)]
+pub struct KeymapDiff {
+    pub from: String,
+    pub to: String,
+    // Gained first, then lost, then changed; by character within each
+    pub changes: Vec<Change>,
+}
+
+impl KeymapDiff {
+    /// Compile and index both keymaps and compare them. `current` is the
+    /// index of the compositor's keymap, if there is one.
+    pub fn compare(from: &KeymapSource, to: &KeymapSource, current: Option<&ComposeIndex>) -> Result<Self, String> {
+        let from_built = build_unless_current(from, current)?;
+        let to_built = build_unless_current(to, current)?;
+        // None only when the current index is there to use
+        let from_index = from_built.as_ref().or(current).ok_or("No keymap to compare")?;
+        let to_index = to_built.as_ref().or(current).ok_or("No keymap to compare")?;
+        Ok(Self::between(from, from_index, to, to_index))
+    }
+
+    fn between(from: &KeymapSource, from_index: &ComposeIndex, to: &KeymapSource, to_index: &ComposeIndex) -> Self {
+        let before = from_index.routes();
+        let after = to_index.routes();
+        // The same routes in another order is no change
+        let same = |a: &[String], b: &[String]| {
+            let (mut a, mut b) = (a.to_vec(), b.to_vec());
+            a.sort();
+            b.sort();
+            a == b
+        };
+
+        let mut changes: Vec<Change> = Vec::new();
+        for (character, routes) in &after {
+            match before.get(character) {
+                None => changes.push(Change {
+                    kind: ChangeKind::Gained,
+                    character: character.clone(),
+                    before: Vec::new(),
+                    after: routes.clone(),
+                }),
+                Some(old) if !same(old, routes) => changes.push(Change {
+                    kind: ChangeKind::Changed,
+                    character: character.clone(),
+                    before: old.clone(),
+                    after: routes.clone(),
+                }),
+                Some(_) => {}
+            }
+        }
+        changes.extend(before.iter().filter(|(character, _)| !after.contains_key(*character)).map(|(character, routes)| {
+            Change { kind: ChangeKind::Lost, character: character.clone(), before: routes.clone(), after: Vec::new() }
+        }));
+        changes.sort_by(|a, b| (a.kind, &a.character).cmp(&(b.kind, &b.character)));
+
+        KeymapDiff { from: from.to_string(), to: to.to_string(), changes }
+    }
+
+    pub fn count(&self, kind: ChangeKind) -> usize {
+        self.changes.iter().filter(|change| change.kind == kind).count()
+    }
+
+    /// "12 gained · 3 lost · 40 changed"
+    pub fn summary(&self) -> String {
+        format!(
+            "{} gained · {} lost · {} changed",
+            self.count(ChangeKind::Gained),
+            self.count(ChangeKind::Lost),
+            self.count(ChangeKind::Changed),
+        )
+    }
+
+    /// The changes as an aligned plain-text table, one character per line
+    pub fn table(&self) -> String {
+        let rows: Vec<[String; 4]> = self
+            .changes
+            .iter()
+            .map(|change| {
+                [
+                    change.kind.marker().to_string(),
+                    display_text(&change.character),
+                    route_text(&change.before),
+                    route_text(&change.after),
+                ]
+            })
+            .collect();
+        let header = ["".to_string(), "".to_string(), self.from.clone(), self.to.clone()];
+        let width = |column: usize| {
+            rows.iter().chain([&header]).map(|row| text_width(&row[column])).max().unwrap_or(0)
+        };
+        let widths = [width(0), width(1), width(2)];
+
+        let mut table = format!("{} → {}: {}\n", self.from, self.to, self.summary());
+        for row in [&header].into_iter().chain(&rows) {
+            let line = format!(
+                "{} {}  {}  {}",
+                pad(&row[0], widths[0]),
+                pad(&row[1], widths[1]),
+                pad(&row[2], widths[2]),
+                row[3],
+            );
+            table.push_str(line.trim_end());
+            table.push('\n');
+        }
+        table
+    }
+}
+
+/// A new index for the keymap, or None if it is the current one and that
+/// is already indexed
+fn build_unless_current(source: &KeymapSource, current: Option<&ComposeIndex>) -> Result<Option<ComposeIndex>, String> {
+    match (source, current) {
+        (KeymapSource::Current, Some(_)) => Ok(None),
+        _ => cache::index_for(&source.load()?).map(Some),
+    }
+}
+
+/// Routes with single spaces between steps, or "—" for none
+pub fn route_text(routes: &[String]) -> String {
+    if routes.is_empty() {
+        "—".to_string()
+    } else {
+        routes.iter().map(|route| route.replace("  ", " ")).collect::<Vec<_>>().join(" · ")
+    }
+}
+
+/// Terminal columns text takes up: none for combining marks and variation
+/// selectors, two for emoji and East Asian wide characters
+fn text_width(text: &str) -> usize {
+    let mut chars = text.chars().peekable();
+    let mut width = 0;
+    while let Some(c) = chars.next() {
+        width += match c as u32 {
+            _ if is_combining(c) => 0,
+            0x200B..=0x200D | 0xFE00..=0xFE0F => 0,
+            // Emoji presentation makes a text symbol (❤) wide
+            _ if chars.peek() == Some(&'\u{FE0F}') => 2,
+            0x1100..=0x115F | 0x2E80..=0x303E | 0x3041..=0xA4CF | 0xAC00..=0xD7A3 | 0xF900..=0xFAFF | 0xFE30..=0xFE4F
+            | 0xFF00..=0xFF60 | 0xFFE0..=0xFFE6 | 0x1F000..=0x1FAFF | 0x20000..=0x3FFFD => 2,
+            _ => 1,
+        };
+    }
+    width
+}
+
+/// Text followed by spaces up to `width` columns
+fn pad(text: &str, width: usize) -> String {
+    format!("{}{}", text, " ".repeat(width.saturating_sub(text_width(text))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_keymap_sources() {
+        assert_eq!(KeymapSource::parse("current"), Ok(KeymapSource::Current));
+        assert_eq!(KeymapSource::parse("./keymap.xkb"), Ok(KeymapSource::File(PathBuf::from("./keymap.xkb"))));
+        let source = KeymapSource::parse("us(altgr-intl),compose:ralt").unwrap();
+        assert_eq!(
+            source,
+            KeymapSource::Names {
+                layout: "us".to_string(),
+                variant: "altgr-intl".to_string(),
+                options: "compose:ralt".to_string(),
+            }
+        );
+        assert_eq!(source.to_string(), "us(altgr-intl),compose:ralt");
+        assert!(KeymapSource::parse("us(intl").is_err());
+        assert!(KeymapSource::parse("compose:ralt").is_err());
+    }
+
+    #[test]
+    fn parses_several_layouts() {
+        assert_eq!(
+            KeymapSource::parse("us,de"),
+            Ok(KeymapSource::Names { layout: "us,de".to_string(), variant: String::new(), options: String::new() })
+        );
+        let source = KeymapSource::parse("us,de(nodeadkeys),compose:ralt,grp:alt_shift_toggle").unwrap();
+        assert_eq!(
+            source,
+            KeymapSource::Names {
+                layout: "us,de".to_string(),
+                variant: ",nodeadkeys".to_string(),
+                options: "compose:ralt,grp:alt_shift_toggle".to_string(),
+            }
+        );
+        assert_eq!(source.to_string(), "us,de(nodeadkeys),compose:ralt,grp:alt_shift_toggle");
+    }
+
+    #[test]
+    fn reports_symbols_on_layout_levels() {
+        let from = ComposeIndex::build(&XkbKeymap::from_names("us", "", "").unwrap()).unwrap();
+        let to = ComposeIndex::build(&XkbKeymap::from_names("us", "altgr-intl", "").unwrap()).unwrap();
+        let diff = KeymapDiff::between(&names("us"), &from, &KeymapSource::parse("us(altgr-intl)").unwrap(), &to);
+        let change = |character: &str| diff.changes.iter().find(|change| change.character == character).cloned();
+        for (character, route) in [("×", "AltGr-="), ("¿", "AltGr-/"), ("€", "AltGr-5")] {
+            assert_eq!(
+                change(character),
+                Some(Change { kind: ChangeKind::Gained, character: character.to_string(), before: vec![], after: routes(&[route]) })
+            );
+        }
+    }
+
+    fn names(layout: &str) -> KeymapSource {
+        KeymapSource::Names { layout: layout.to_string(), variant: String::new(), options: String::new() }
+    }
+
+    fn routes(routes: &[&str]) -> Vec<String> {
+        routes.iter().map(|route| route.to_string()).collect()
+    }
+
+    #[test]
+    fn finds_gained_lost_and_changed_characters() {
+        let from = ComposeIndex::from_routes(&[("é", "AltGr-e"), ("ß", "AltGr-s"), ("ñ", "AltGr-n"), ("ü", "AltGr-y")]);
+        let to = ComposeIndex::from_routes(&[
+            ("é", "AltGr-e"),
+            ("ñ", "AltGr-~  n"),
+            ("ü", "AltGr-y"),
+            ("ü", "AltGr-\"  u"),
+            ("ø", "AltGr-o"),
+        ]);
+        let diff = KeymapDiff::between(&names("us"), &from, &names("de"), &to);
+        assert_eq!(
+            diff.changes,
+            [
+                Change { kind: ChangeKind::Gained, character: "ø".to_string(), before: vec![], after: routes(&["AltGr-o"]) },
+                Change { kind: ChangeKind::Lost, character: "ß".to_string(), before: routes(&["AltGr-s"]), after: vec![] },
+                Change {
+                    kind: ChangeKind::Changed,
+                    character: "ñ".to_string(),
+                    before: routes(&["AltGr-n"]),
+                    after: routes(&["AltGr-~  n"]),
+                },
+                // Same shortest route, one more besides it
+                Change {
+                    kind: ChangeKind::Changed,
+                    character: "ü".to_string(),
+                    before: routes(&["AltGr-y"]),
+                    after: routes(&["AltGr-y", "AltGr-\"  u"]),
+                },
+            ]
+        );
+        assert_eq!(diff.summary(), "1 gained · 1 lost · 2 changed");
+    }
+
+    #[test]
+    fn table_columns_line_up() {
+        let from = ComposeIndex::from_routes(&[("é", "AltGr-e"), ("ǘ", "AltGr-;  AltGr-v")]);
+        let to = ComposeIndex::from_routes(&[("ǘ", "AltGr-v")]);
+        let mut diff = KeymapDiff::between(&names("us"), &from, &names("de"), &to);
+        // A combining mark takes no column of its own, an emoji takes two
+        diff.changes.push(Change { kind: ChangeKind::Gained, character: "\u{301}".to_string(), before: vec![], after: routes(&["AltGr-'"]) });
+        diff.changes.push(Change { kind: ChangeKind::Gained, character: "😀".to_string(), before: vec![], after: routes(&["Compose  :  )"]) });
+        assert_eq!(
+            diff.table(),
+            "us → de: 2 gained · 1 lost · 1 changed\n\
+             \x20     us               de\n\
+             - é   AltGr-e          —\n\
+             ~ ǘ   AltGr-; AltGr-v  AltGr-v\n\
+             + ◌́   —                AltGr-'\n\
+             + 😀  —                Compose : )\n"
+        );
+    }
+}
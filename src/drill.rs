@@ -0,0 +1,206 @@
+use crate::compose::ComposeEntry;
+use crate::keyboard::KeyOutput;
+use std::time::{Duration, Instant};
+
+// Turns until a card comes back, by Leitner box. A miss sends the card back
+// to the first box so it shows up again almost immediately.
+const BOX_INTERVALS: [u32; 5] = [2, 4, 8, 16, 32];
+
+struct Card {
+    entry: ComposeEntry,
+    level: usize,
+    due: u32,
+}
+
+/// Outcome of the last attempt, shown under the target
+pub enum Feedback {
+    Correct(Duration),
+    Wrong(String),
+}
+
+/// Practice mode: show a character, let the user type it with the real key
+/// sequence, and repeat the ones they get wrong more often
+pub struct Drill {
+    cards: Vec<Card>,
+    current: usize,
+    turn: u32,
+    started: Instant,
+    // Reveal the key sequence after a miss
+    pub show_hint: bool,
+    pub feedback: Option<Feedback>,
+    // Whether a dead key / Compose sequence is half typed
+    pub pending: bool,
+    pub attempts: u32,
+    pub correct: u32,
+    total_time: Duration,
+}
+
+impl Drill {
+    /// Start a drill over these entries; None if there is nothing to practice
+    pub fn new(entries: Vec<ComposeEntry>) -> Option<Self> {
+        if entries.is_empty() {
+            return None;
+        }
+        let cards = entries
+            .into_iter()
+            .enumerate()
+            .map(|(i, entry)| Card { entry, level: 0, due: i as u32 })
+            .collect();
+
+        Some(Drill {
+            cards,
+            current: 0,
+            turn: 0,
+            started: Instant::now(),
+            show_hint: false,
+            feedback: None,
+            pending: false,
+            attempts: 0,
+            correct: 0,
+            total_time: Duration::ZERO,
+        })
+    }
+
+    pub fn target(&self) -> &ComposeEntry {
+        &self.cards[self.current].entry
+    }
+
+    pub fn average_time(&self) -> Option<Duration> {
+        (self.correct > 0).then(|| self.total_time / self.correct)
+    }
+
+    /// Feed a decoded key press. Returns true if the screen needs a redraw.
+    pub fn handle_key(&mut self, output: KeyOutput) -> bool {
+        match output {
+            KeyOutput::Nothing => false,
+            KeyOutput::Pending => {
+                self.pending = true;
+                true
+            }
+            KeyOutput::Cancelled => {
+                self.pending = false;
+                self.answer(None);
+                true
+            }
+            KeyOutput::Text(text) => {
+                self.pending = false;
+                self.answer(Some(text));
+                true
+            }
+        }
+    }
+
+    fn answer(&mut self, typed: Option<String>) {
+        self.attempts += 1;
+        let card = &mut self.cards[self.current];
+
+        if typed.as_deref() == Some(card.entry.character.as_str()) {
+            let elapsed = self.started.elapsed();
+            self.correct += 1;
+            self.total_time += elapsed;
+            // A card answered after seeing the hint doesn't move up
+            if !self.show_hint {
+                card.level = (card.level + 1).min(BOX_INTERVALS.len() - 1);
+            }
+            card.due = self.turn + BOX_INTERVALS[card.level];
+            self.feedback = Some(Feedback::Correct(elapsed));
+            self.next();
+        } else {
+            // Stay on the same character, now with the keys shown
+            card.level = 0;
+            card.due = self.turn + BOX_INTERVALS[0];
+            self.show_hint = true;
+            self.feedback = Some(Feedback::Wrong(typed.unwrap_or_else(|| "an unknown sequence".to_string())));
+        }
+    }
+
+    /// Move on to the card that is most overdue, avoiding an immediate repeat
+    fn next(&mut self) {
+        self.turn += 1;
+        let previous = self.current;
+        self.current = (0..self.cards.len())
+            .filter(|&i| i != previous || self.cards.len() == 1)
+            .min_by_key(|&i| (self.cards[i].due, self.cards[i].level))
+            .unwrap_or(previous);
+        self.show_hint = false;
+        self.started = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drill(characters: &[&str]) -> Drill {
+        let entries = characters
+            .iter()
+            .map(|c| ComposeEntry { character: c.to_string(), key_sequence: "AltGr-x".to_string(), alternatives: Vec::new() })
+            .collect();
+        Drill::new(entries).unwrap()
+    }
+
+    fn type_text(drill: &mut Drill, text: &str) {
+        drill.handle_key(KeyOutput::Text(text.to_string()));
+    }
+
+    #[test]
+    fn correct_answer_promotes_the_card() {
+        let mut drill = drill(&["é", "ñ"]);
+        type_text(&mut drill, "é");
+        assert_eq!(drill.cards[0].level, 1);
+        assert_eq!(drill.cards[0].due, BOX_INTERVALS[1]);
+        assert_eq!(drill.correct, 1);
+        assert_eq!(drill.target().character, "ñ");
+    }
+
+    #[test]
+    fn miss_resets_the_card_and_keeps_it() {
+        let mut drill = drill(&["é"]);
+        type_text(&mut drill, "é");
+        type_text(&mut drill, "é");
+        assert_eq!(drill.cards[0].level, 2);
+
+        type_text(&mut drill, "e");
+        assert_eq!(drill.cards[0].level, 0);
+        assert_eq!(drill.target().character, "é");
+        assert!(drill.show_hint);
+        assert_eq!(drill.attempts, 3);
+        assert_eq!(drill.correct, 2);
+    }
+
+    #[test]
+    fn answer_after_hint_does_not_promote() {
+        let mut drill = drill(&["é", "ñ"]);
+        drill.handle_key(KeyOutput::Pending);
+        assert!(drill.pending);
+        drill.handle_key(KeyOutput::Cancelled);
+        assert!(!drill.pending);
+        assert!(drill.show_hint);
+
+        type_text(&mut drill, "é");
+        assert_eq!(drill.cards[0].level, 0);
+        assert!(!drill.show_hint);
+        assert_eq!(drill.target().character, "ñ");
+    }
+
+    #[test]
+    fn no_immediate_repeat_and_most_overdue_first() {
+        let mut drill = drill(&["é", "ñ", "ü"]);
+        // A miss makes é due soonest, but the next card is still another one
+        type_text(&mut drill, "x");
+        type_text(&mut drill, "é");
+        assert_eq!(drill.target().character, "ñ");
+        type_text(&mut drill, "ñ");
+        assert_eq!(drill.target().character, "é");
+        type_text(&mut drill, "é");
+        assert_eq!(drill.target().character, "ü");
+
+        let mut seen = vec![drill.target().character.clone()];
+        for _ in 0..10 {
+            let target = drill.target().character.clone();
+            type_text(&mut drill, &target);
+            assert_ne!(Some(&drill.target().character), seen.last());
+            seen.push(drill.target().character.clone());
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+
+// How many recently copied characters to remember
+const RECENT_LIMIT: usize = 20;
+
+/// Recently copied and pinned characters, persisted in
+/// $XDG_STATE_HOME/kbdviz/history.toml
+pub struct History {
+    path: Option<PathBuf>,
+    pub favorites: Vec<String>,
+    // Most recent first
+    pub recent: Vec<String>,
+    // How often each character was copied, for ranking
+    counts: HashMap<String, u32>,
+}
+
+impl History {
+    /// Load the history file; a missing or unreadable file gives an empty history
+    pub fn load() -> Self {
+        let path = state_home().map(|dir| dir.join("kbdviz").join("history.toml"));
+        let mut history = History {
+            path,
+            favorites: Vec::new(),
+            recent: Vec::new(),
+            counts: HashMap::new(),
+        };
+
+        let Some(path) = &history.path else {
+            return history;
+        };
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return history,
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", path.display(), e);
+                return history;
+            }
+        };
+        let table: toml::Table = match contents.parse() {
+            Ok(table) => table,
+            Err(e) => {
+                eprintln!("Ignoring {}: {}", path.display(), e);
+                return history;
+            }
+        };
+
+        history.favorites = string_list(&table, "favorites");
+        history.recent = string_list(&table, "recent");
+        history.recent.truncate(RECENT_LIMIT);
+        if let Some(counts) = table.get("counts").and_then(|value| value.as_table()) {
+            for (character, count) in counts {
+                if let Some(count) = count.as_integer().and_then(|n| u32::try_from(n).ok()) {
+                    history.counts.insert(character.clone(), count);
+                }
+            }
+        }
+        history
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+
+        let mut table = toml::Table::new();
+        table.insert("favorites".to_string(), self.favorites.clone().into());
+        table.insert("recent".to_string(), self.recent.clone().into());
+        let counts: toml::Table = self.counts
+            .iter()
+            .map(|(character, &count)| (character.clone(), i64::from(count).into()))
+            .collect();
+        table.insert("counts".to_string(), counts.into());
+
+        let result = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(path, table.to_string()));
+        if let Err(e) = result {
+            eprintln!("Failed to write {}: {}", path.display(), e);
+        }
+    }
+
+    /// An empty history that is never saved
+    #[cfg(test)]
+    pub fn in_memory() -> Self {
+        History { path: None, favorites: Vec::new(), recent: Vec::new(), counts: HashMap::new() }
+    }
+
+    /// Number of times a character has been copied
+    pub fn copy_count(&self, character: &str) -> u32 {
+        self.counts.get(character).copied().unwrap_or(0)
+    }
+
+    pub fn is_favorite(&self, character: &str) -> bool {
+        self.favorites.iter().any(|c| c == character)
+    }
+
+    /// Move a copied character to the front of the recent list
+    pub fn record_copy(&mut self, character: &str) {
+        self.recent.retain(|c| c != character);
+        self.recent.insert(0, character.to_string());
+        self.recent.truncate(RECENT_LIMIT);
+        *self.counts.entry(character.to_string()).or_default() += 1;
+        self.save();
+    }
+
+    /// Pin or unpin a character, returns true if it is now pinned
+    pub fn toggle_favorite(&mut self, character: &str) -> bool {
+        let pinned = if self.is_favorite(character) {
+            self.favorites.retain(|c| c != character);
+            false
+        } else {
+            self.favorites.push(character.to_string());
+            true
+        };
+        self.save();
+        pinned
+    }
+
+    /// Drop a character from both favorites and the recent list
+    pub fn forget(&mut self, character: &str) {
+        self.favorites.retain(|c| c != character);
+        self.recent.retain(|c| c != character);
+        self.counts.remove(character);
+        self.save();
+    }
+
+    /// Favorites first, then recent characters that aren't pinned
+    pub fn characters(&self) -> Vec<&str> {
+        let mut characters: Vec<&str> = self.favorites.iter().map(String::as_str).collect();
+        for character in &self.recent {
+            if !self.is_favorite(character) {
+                characters.push(character);
+            }
+        }
+        characters
+    }
+}
+
+fn string_list(table: &toml::Table, key: &str) -> Vec<String> {
+    table
+        .get(key)
+        .and_then(|value| value.as_array())
+        .map(|items| items.iter().filter_map(|item| item.as_str().map(str::to_string)).collect())
+        .unwrap_or_default()
+}
+
+/// $XDG_STATE_HOME, falling back to ~/.local/state
+fn state_home() -> Option<PathBuf> {
+    env::var_os("XDG_STATE_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state")))
+}
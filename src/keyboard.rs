@@ -1,7 +1,10 @@
+use crate::keylabel::dead_key_label;
+use std::env;
 use xkbcommon::xkb;
 
 /// Wrapper around XKB keymap for querying keyboard layout
 pub struct XkbKeymap {
+    context: xkb::Context,
     keymap: xkb::Keymap,
 }
 
@@ -17,11 +20,276 @@ impl XkbKeymap {
         )
         .ok_or("Failed to parse keymap string")?;
 
-        Ok(Self { keymap })
+        Ok(Self { context, keymap })
+    }
+
+    /// Compile a keymap from RMLVO names, as if the user had configured them
+    /// ("us", "intl", "compose:ralt"); empty strings mean the defaults
+    pub fn from_names(layout: &str, variant: &str, options: &str) -> Result<Self, String> {
+        let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+        let options = (!options.is_empty()).then(|| options.to_string());
+        let keymap = xkb::Keymap::new_from_names(
+            &context,
+            "evdev",
+            "pc105",
+            layout,
+            variant,
+            options,
+            xkb::KEYMAP_COMPILE_NO_FLAGS,
+        )
+        .ok_or_else(|| format!("Failed to compile layout {}({})", layout, variant))?;
+
+        Ok(Self { context, keymap })
     }
 
     /// Get the XKB keymap
     pub fn keymap(&self) -> &xkb::Keymap {
         &self.keymap
     }
+
+    /// The locale's Compose table, which also defines what dead keys do
+    pub fn compose_table(&self) -> Option<xkb::compose::Table> {
+        xkb::compose::Table::new_from_locale(&self.context, &locale(), xkb::compose::COMPILE_NO_FLAGS).ok()
+    }
+}
+
+/// Result of feeding a key press through the keyboard state
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeyOutput {
+    /// The key (or a finished dead key / Compose sequence) produced text
+    Text(String),
+    /// A dead key or Compose sequence is in progress
+    Pending,
+    /// A dead key or Compose sequence was started but didn't match
+    Cancelled,
+    /// Modifiers and other keys that produce nothing
+    Nothing,
+}
+
+/// Our own xkb state plus the locale's Compose state machine, driven by the
+/// key events the overlay receives. This lets us check what a sequence of
+/// presses actually produces, including dead keys.
+pub struct KeyState {
+    keymap: xkb::Keymap,
+    state: xkb::State,
+    compose: Option<xkb::compose::State>,
+    // Keysyms fed into an unfinished Compose sequence
+    compose_sequence: Vec<xkb::Keysym>,
+    // Last key that produced something, for the decoder
+    last_key: Option<xkb::Keycode>,
+    last_output: Option<String>,
+}
+
+/// Snapshot of the keyboard state for the live decoder
+pub struct Decoded {
+    pub modifiers: Vec<&'static str>,
+    pub layout: String,
+    // Shift level of the last pressed key (1-based, like xkb documentation)
+    pub level: Option<u32>,
+    // Keysym names of a half-typed dead key / Compose sequence
+    pub pending: Vec<String>,
+    pub last_output: Option<String>,
+    // What each key of the main block produces right now, by keyboard row:
+    // (output, is a dead key)
+    pub rows: Vec<Vec<(String, bool)>>,
+}
+
+// Real modifiers and how they are usually labelled on the keyboard
+const MODIFIER_LABELS: &[(&str, &str)] = &[
+    (xkb::MOD_NAME_SHIFT, "Shift"),
+    (xkb::MOD_NAME_CAPS, "Caps Lock"),
+    (xkb::MOD_NAME_CTRL, "Ctrl"),
+    (xkb::MOD_NAME_ALT, "Alt"),
+    (xkb::MOD_NAME_LOGO, "Super"),
+    (xkb::MOD_NAME_ISO_LEVEL3_SHIFT, "AltGr"),
+];
+
+impl KeyState {
+    pub fn new(xkb: &XkbKeymap) -> Self {
+        let compose = xkb
+            .compose_table()
+            .map(|table| xkb::compose::State::new(&table, xkb::compose::STATE_NO_FLAGS));
+        if compose.is_none() {
+            eprintln!("No Compose table for this locale, dead keys won't be recognized");
+        }
+
+        KeyState {
+            keymap: xkb.keymap().clone(),
+            state: xkb::State::new(xkb.keymap()),
+            compose,
+            compose_sequence: Vec::new(),
+            last_key: None,
+            last_output: None,
+        }
+    }
+
+    /// Handle a key press (Wayland key code, without the +8 xkb offset)
+    pub fn press(&mut self, raw_code: u32) -> KeyOutput {
+        let keycode = xkb::Keycode::new(raw_code + 8);
+        // Query before updating, so the key's own modifier change doesn't apply
+        let keysym = self.state.key_get_one_sym(keycode);
+        let text = self.state.key_get_utf8(keycode);
+        self.state.update_key(keycode, xkb::KeyDirection::Down);
+
+        let output = self.feed_compose(keysym).unwrap_or_else(|| {
+            if text.is_empty() || text.chars().all(char::is_control) {
+                KeyOutput::Nothing
+            } else {
+                KeyOutput::Text(text)
+            }
+        });
+        if output != KeyOutput::Nothing {
+            self.last_key = Some(keycode);
+        }
+        if let KeyOutput::Text(text) = &output {
+            self.last_output = Some(text.clone());
+        }
+        output
+    }
+
+    /// Run a keysym through the Compose state machine; None if it isn't
+    /// part of a sequence
+    fn feed_compose(&mut self, keysym: xkb::Keysym) -> Option<KeyOutput> {
+        let compose = self.compose.as_mut()?;
+        if compose.feed(keysym) != xkb::compose::FeedResult::Accepted {
+            return None;
+        }
+        let output = match compose.status() {
+            xkb::compose::Status::Composing => {
+                self.compose_sequence.push(keysym);
+                return Some(KeyOutput::Pending);
+            }
+            xkb::compose::Status::Composed => KeyOutput::Text(compose.utf8().unwrap_or_default()),
+            xkb::compose::Status::Cancelled => KeyOutput::Cancelled,
+            xkb::compose::Status::Nothing => return None,
+        };
+        compose.reset();
+        self.compose_sequence.clear();
+        Some(output)
+    }
+
+    pub fn release(&mut self, raw_code: u32) {
+        self.state.update_key(xkb::Keycode::new(raw_code + 8), xkb::KeyDirection::Up);
+    }
+
+    /// Forget any half-typed dead key or Compose sequence
+    pub fn reset_compose(&mut self) {
+        if let Some(compose) = &mut self.compose {
+            compose.reset();
+        }
+        self.compose_sequence.clear();
+    }
+
+    /// Keys already held when the overlay gained focus
+    pub fn enter(&mut self, raw_codes: &[u32]) {
+        for &raw_code in raw_codes {
+            self.state.update_key(xkb::Keycode::new(raw_code + 8), xkb::KeyDirection::Down);
+        }
+    }
+
+    /// The compositor releases all keys when focus leaves
+    pub fn leave(&mut self) {
+        self.state = xkb::State::new(&self.keymap);
+        self.reset_compose();
+    }
+
+    /// Apply the lock state and active layout reported by the compositor.
+    /// Held modifiers come from our own key tracking, which also sees AltGr.
+    pub fn sync_modifiers(&mut self, caps_lock: bool, num_lock: bool, layout: u32) {
+        let mut locked = self.state.serialize_mods(xkb::STATE_MODS_LOCKED);
+        for (name, active) in [(xkb::MOD_NAME_CAPS, caps_lock), (xkb::MOD_NAME_NUM, num_lock)] {
+            let index = self.keymap.mod_get_index(name);
+            if index == xkb::MOD_INVALID {
+                continue;
+            }
+            if active {
+                locked |= 1 << index;
+            } else {
+                locked &= !(1 << index);
+            }
+        }
+        self.state.update_mask(
+            self.state.serialize_mods(xkb::STATE_MODS_DEPRESSED),
+            self.state.serialize_mods(xkb::STATE_MODS_LATCHED),
+            locked,
+            0,
+            0,
+            layout,
+        );
+    }
+
+    /// Labels of the active modifiers, e.g. ["Shift", "AltGr"]
+    pub fn held_modifiers(&self) -> Vec<&'static str> {
+        MODIFIER_LABELS
+            .iter()
+            .filter(|(name, _)| self.state.mod_name_is_active(name, xkb::STATE_MODS_EFFECTIVE))
+            .map(|(_, label)| *label)
+            .collect()
+    }
+
+    /// Name of the active layout, e.g. "English (US)"
+    pub fn layout_name(&self) -> String {
+        let layout_index = self.state.serialize_layout(xkb::STATE_LAYOUT_EFFECTIVE);
+        self.keymap.layout_get_name(layout_index).to_string()
+    }
+
+    /// Current modifiers, level, pending sequence and the output of every key
+    pub fn decode(&self) -> Decoded {
+        let modifiers = self.held_modifiers();
+
+        let level = self
+            .last_key
+            .or_else(|| self.keymap.key_by_name("AC01"))
+            .map(|key| self.state.key_get_level(key, self.state.key_get_layout(key)) + 1);
+
+        Decoded {
+            modifiers,
+            layout: self.layout_name(),
+            level,
+            pending: self.compose_sequence.iter().map(|&keysym| xkb::keysym_get_name(keysym)).collect(),
+            last_output: self.last_output.clone(),
+            rows: self.key_rows(),
+        }
+    }
+
+    /// Outputs of the main key block, row by row, under the current state
+    fn key_rows(&self) -> Vec<Vec<(String, bool)>> {
+        let rows: [(&str, usize, Option<&str>, Option<&str>); 4] = [
+            ("AE", 13, Some("TLDE"), None),
+            ("AD", 12, None, Some("BKSL")),
+            ("AC", 11, None, None),
+            ("AB", 10, Some("LSGT"), None),
+        ];
+
+        rows.iter()
+            .map(|&(prefix, count, before, after)| {
+                let names = before
+                    .map(str::to_string)
+                    .into_iter()
+                    .chain((1..=count).map(|n| format!("{}{:02}", prefix, n)))
+                    .chain(after.map(str::to_string));
+                names
+                    .filter_map(|name| self.keymap.key_by_name(&name))
+                    // Skip key codes the layout doesn't use (e.g. AE13 on most layouts)
+                    .filter(|&key| !self.keymap.key_get_syms_by_level(key, self.state.key_get_layout(key), 0).is_empty())
+                    .map(|key| {
+                        let keysym = self.state.key_get_one_sym(key);
+                        match dead_key_label(&xkb::keysym_get_name(keysym)) {
+                            Some(label) => (label.to_string(), true),
+                            None => (self.state.key_get_utf8(key).chars().filter(|c| !c.is_control()).collect(), false),
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Locale for the Compose table, following the same variables as libX11
+pub fn locale() -> std::ffi::OsString {
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .into_iter()
+        .filter_map(env::var_os)
+        .find(|value| !value.is_empty())
+        .unwrap_or_else(|| "C".into())
 }
@@ -0,0 +1,182 @@
+use std::env;
+use xkbcommon::xkb;
+
+// Languages with translated key names, in the column order of KEY_NAMES
+const LANGUAGES: [&str; 5] = ["en", "de", "fr", "es", "nl"];
+
+/// Friendly names for keys whose base level isn't a printable character,
+/// by XKB key name. No "-" anywhere: key sequences use it to join modifiers
+/// and keys.
+const KEY_NAMES: &[(&str, [&str; 5])] = &[
+    ("LSGT", ["< > key left of Z", "< > Taste links von Y", "touche < > à gauche de W", "tecla < > a la izquierda de Z", "< > toets links van Z"]),
+    ("TLDE", ["key left of 1", "Taste links von 1", "touche à gauche de 1", "tecla a la izquierda de 1", "toets links van 1"]),
+    ("BKSL", ["\\ key", "# Taste", "touche à gauche d'Entrée", "tecla ç", "\\ toets"]),
+    ("AB11", ["key right of /", "Taste links der rechten Umschalttaste", "touche à droite de !", "tecla a la izquierda de Mayús derecha", "toets rechts van /"]),
+    ("AE13", ["Yen key", "Yen Taste", "touche Yen", "tecla Yen", "Yen toets"]),
+    ("COMP", ["Menu", "Menü", "Menu", "Menú", "Menu"]),
+    ("MENU", ["Menu", "Menü", "Menu", "Menú", "Menu"]),
+    ("RALT", ["Right Alt", "Alt Gr", "Alt Gr", "Alt Gr", "Rechter Alt"]),
+    ("LALT", ["Left Alt", "Alt", "Alt", "Alt", "Linker Alt"]),
+    ("RCTL", ["Right Ctrl", "Strg rechts", "Ctrl droit", "Ctrl derecho", "Rechter Ctrl"]),
+    ("LCTL", ["Left Ctrl", "Strg links", "Ctrl gauche", "Ctrl izquierdo", "Linker Ctrl"]),
+    ("LWIN", ["Super", "Super", "Super", "Super", "Super"]),
+    ("RWIN", ["Right Super", "Super rechts", "Super droit", "Super derecho", "Rechter Super"]),
+    ("CAPS", ["Caps Lock", "Feststelltaste", "Verr. Maj", "Bloq Mayús", "Caps Lock"]),
+    ("SPCE", ["Space", "Leertaste", "Espace", "Espacio", "Spatie"]),
+    ("RTRN", ["Enter", "Eingabe", "Entrée", "Intro", "Enter"]),
+    ("BKSP", ["Backspace", "Rücktaste", "Retour arrière", "Retroceso", "Backspace"]),
+    ("TAB", ["Tab", "Tab", "Tab", "Tab", "Tab"]),
+    ("ESC", ["Esc", "Esc", "Échap", "Esc", "Esc"]),
+    ("KPDL", ["Keypad .", "Ziffernblock ,", "Pavé num. .", "Teclado num. .", "Numeriek ."]),
+    ("HENK", ["Henkan", "Henkan", "Henkan", "Henkan", "Henkan"]),
+    ("MUHE", ["Muhenkan", "Muhenkan", "Muhenkan", "Muhenkan", "Muhenkan"]),
+    ("HKTG", ["Hiragana/Katakana", "Hiragana/Katakana", "Hiragana/Katakana", "Hiragana/Katakana", "Hiragana/Katakana"]),
+];
+
+/// Turns keycodes into the labels shown on keycaps: the character printed
+/// on the key where there is one, otherwise a friendly name in the user's
+/// language
+pub struct KeyLabels {
+    // Column in KEY_NAMES
+    language: usize,
+}
+
+impl KeyLabels {
+    /// Use the language of LC_ALL / LC_MESSAGES / LANG, falling back to English
+    pub fn from_locale() -> Self {
+        let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .into_iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default();
+        Self::for_locale(&locale)
+    }
+
+    /// Use the language of a locale name like "de_DE.UTF-8"
+    fn for_locale(locale: &str) -> Self {
+        let code = locale.split(['_', '.', '@']).next().unwrap_or("");
+        let language = LANGUAGES.iter().position(|l| *l == code).unwrap_or(0);
+        KeyLabels { language }
+    }
+
+    /// Label for a key: its base-level character, the accent of a dead key,
+    /// a friendly name, the keysym name, or as a last resort the XKB name
+    pub fn label(&self, keymap: &xkb::Keymap, keycode: xkb::Keycode) -> String {
+        let key_name = keymap.key_get_name(keycode).unwrap_or("?");
+        let Some(&keysym) = keymap.key_get_syms_by_level(keycode, 0, 0).first() else {
+            return self.friendly_name(key_name).unwrap_or(key_name).to_string();
+        };
+
+        if let Some(c) = char::from_u32(xkb::keysym_to_utf32(keysym)) {
+            if !c.is_control() && !c.is_whitespace() {
+                return c.to_string();
+            }
+        }
+
+        let keysym_name = xkb::keysym_get_name(keysym);
+        if let Some(accent) = dead_key_label(&keysym_name) {
+            return accent.to_string();
+        }
+        if let Some(name) = self.friendly_name(key_name) {
+            return name.to_string();
+        }
+        if keysym != xkb::Keysym::NoSymbol {
+            return keysym_name;
+        }
+        key_name.to_string()
+    }
+
+    fn friendly_name(&self, key_name: &str) -> Option<&'static str> {
+        KEY_NAMES
+            .iter()
+            .find(|(name, _)| *name == key_name)
+            .map(|(_, labels)| labels[self.language])
+    }
+}
+
+/// Spacing version of a dead key's accent, e.g. dead_acute → ´
+pub fn dead_key_label(keysym_name: &str) -> Option<&'static str> {
+    let label = match keysym_name.strip_prefix("dead_")? {
+        "acute" => "´",
+        "grave" => "`",
+        "circumflex" => "^",
+        "diaeresis" => "¨",
+        "tilde" => "~",
+        "cedilla" => "¸",
+        "ogonek" => "˛",
+        "caron" => "ˇ",
+        "breve" => "˘",
+        "macron" => "¯",
+        "abovedot" => "˙",
+        "abovering" => "˚",
+        "doubleacute" => "˝",
+        "stroke" => "/",
+        "belowdot" => ".",
+        "greek" => "µ",
+        "currency" => "¤",
+        "iota" => "ͺ",
+        "abovecomma" => "᾿",
+        "abovereversedcomma" => "῾",
+        _ => "◌",
+    };
+    Some(label)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keymap(layout: &str, variant: &str) -> xkb::Keymap {
+        let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+        xkb::Keymap::new_from_names(&context, "evdev", "pc105", layout, variant, None, xkb::KEYMAP_COMPILE_NO_FLAGS).unwrap()
+    }
+
+    fn label_of(labels: &KeyLabels, keymap: &xkb::Keymap, key_name: &str) -> String {
+        labels.label(keymap, keymap.key_by_name(key_name).unwrap())
+    }
+
+    #[test]
+    fn names_never_contain_the_sequence_separator() {
+        for (key, names) in KEY_NAMES {
+            for name in names {
+                assert!(!name.contains('-'), "{}: {}", key, name);
+            }
+        }
+    }
+
+    #[test]
+    fn language_comes_from_the_locale() {
+        assert_eq!(KeyLabels::for_locale("de_DE.UTF-8").language, 1);
+        assert_eq!(KeyLabels::for_locale("nl_BE@euro").language, 4);
+        // Languages without translations, and the C locale, get English
+        assert_eq!(KeyLabels::for_locale("pt_BR.UTF-8").language, 0);
+        assert_eq!(KeyLabels::for_locale("C").language, 0);
+        assert_eq!(KeyLabels::for_locale("").language, 0);
+    }
+
+    #[test]
+    fn labels_fall_back_in_order() {
+        let keymap = keymap("us", "intl");
+        let english = KeyLabels::for_locale("en_US.UTF-8");
+        let german = KeyLabels::for_locale("de_DE.UTF-8");
+
+        // Printed character, then the accent of a dead key
+        assert_eq!(label_of(&english, &keymap, "AC01"), "a");
+        assert_eq!(label_of(&english, &keymap, "AC11"), "´");
+        // Friendly name in the user's language
+        assert_eq!(label_of(&english, &keymap, "SPCE"), "Space");
+        assert_eq!(label_of(&german, &keymap, "SPCE"), "Leertaste");
+        // Keysym name for keys without a friendly name
+        assert_eq!(label_of(&english, &keymap, "FK01"), "F1");
+
+        // XKB key name for keys without any symbols
+        let keycode = (keymap.min_keycode().raw()..=keymap.max_keycode().raw())
+            .map(xkb::Keycode::new)
+            .find(|&keycode| {
+                keymap.key_get_name(keycode).is_some_and(|name| !KEY_NAMES.iter().any(|(n, _)| *n == name))
+                    && keymap.key_get_syms_by_level(keycode, 0, 0).is_empty()
+            })
+            .unwrap();
+        assert_eq!(english.label(&keymap, keycode), keymap.key_get_name(keycode).unwrap());
+    }
+}
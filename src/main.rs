@@ -1,22 +1,48 @@
+mod cache;
+mod charinfo;
 mod compose;
+mod composefile;
+mod config;
+mod diff;
+mod drill;
+mod history;
+mod rank;
 mod keyboard;
+mod keylabel;
+mod suggest;
+mod theme;
 mod ui;
+mod xcompose;
 
+use charinfo::Representation;
 use compose::ComposeIndex;
-use keyboard::XkbKeymap;
-use std::env;
+use config::{Action, Config};
+use diff::KeymapDiff;
+use history::History;
+use keyboard::{KeyState, XkbKeymap};
 use smithay_client_toolkit::{
     compositor::{CompositorHandler, CompositorState},
     delegate_compositor, delegate_keyboard, delegate_layer, delegate_output, delegate_pointer,
     delegate_registry, delegate_seat, delegate_shm,
     output::{OutputHandler, OutputState},
     reexports::{
-        calloop::{EventLoop, LoopSignal},
+        calloop::{
+            channel::{self, Sender},
+            EventLoop, LoopSignal,
+        },
         calloop_wayland_source::WaylandSource,
         client::{
+            delegate_noop,
             globals::registry_queue_init,
             protocol::{wl_keyboard, wl_output, wl_pointer, wl_seat, wl_surface},
-            Connection, QueueHandle,
+            Connection, Dispatch, QueueHandle,
+        },
+        protocols::wp::{
+            fractional_scale::v1::client::{
+                wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1,
+                wp_fractional_scale_v1::{self, WpFractionalScaleV1},
+            },
+            viewporter::client::{wp_viewport::WpViewport, wp_viewporter::WpViewporter},
         },
     },
     registry::{ProvidesRegistryState, RegistryState},
@@ -28,7 +54,7 @@ use smithay_client_toolkit::{
     },
     shell::{
         wlr_layer::{
-            Anchor, KeyboardInteractivity, Layer, LayerShell, LayerShellHandler, LayerSurface,
+            KeyboardInteractivity, Layer, LayerShell, LayerShellHandler, LayerSurface,
             LayerSurfaceConfigure,
         },
         WaylandSurface,
@@ -38,86 +64,8 @@ use smithay_client_toolkit::{
 use std::sync::Arc;
 use ui::CharRefUI;
 
-const WINDOW_WIDTH: u32 = 280;
-const WINDOW_HEIGHT: u32 = 420;
-
-#[derive(Clone)]
-struct Config {
-    anchor: Anchor,
-    margin: u32,
-    initial_char: Option<char>,
-}
-
-impl Config {
-    fn from_args() -> Result<Self, String> {
-        let args: Vec<String> = env::args().collect();
-        let mut anchor = Anchor::empty(); // centered by default
-        let mut margin = 0u32;
-        let mut initial_char = None;
-
-        let mut i = 1;
-        while i < args.len() {
-            match args[i].as_str() {
-                "--help" | "-h" => {
-                    eprintln!("Usage: kbdviz [OPTIONS]");
-                    eprintln!();
-                    eprintln!("Options:");
-                    eprintln!("  --anchor <POS>     Position: top-left, top-right, bottom-left,");
-                    eprintln!("                     bottom-right, top, bottom, left, right, center");
-                    eprintln!("  --margin <PX>      Margin from screen edges (default: 0)");
-                    eprintln!("  --char <LETTER>    Show variants for this character on startup");
-                    eprintln!("  -h, --help         Show this help");
-                    std::process::exit(0);
-                }
-                "--anchor" => {
-                    i += 1;
-                    if i >= args.len() {
-                        return Err("--anchor requires a value".to_string());
-                    }
-                    anchor = match args[i].as_str() {
-                        "top-left" => Anchor::TOP | Anchor::LEFT,
-                        "top-right" => Anchor::TOP | Anchor::RIGHT,
-                        "bottom-left" => Anchor::BOTTOM | Anchor::LEFT,
-                        "bottom-right" => Anchor::BOTTOM | Anchor::RIGHT,
-                        "top" => Anchor::TOP,
-                        "bottom" => Anchor::BOTTOM,
-                        "left" => Anchor::LEFT,
-                        "right" => Anchor::RIGHT,
-                        "center" => Anchor::empty(),
-                        other => return Err(format!("Unknown anchor: {}", other)),
-                    };
-                }
-                "--margin" => {
-                    i += 1;
-                    if i >= args.len() {
-                        return Err("--margin requires a value".to_string());
-                    }
-                    margin = args[i].parse().map_err(|_| "Invalid margin value")?;
-                }
-                "--char" => {
-                    i += 1;
-                    if i >= args.len() {
-                        return Err("--char requires a value".to_string());
-                    }
-                    let c = args[i].chars().next().ok_or("--char requires a character")?;
-                    if !c.is_alphabetic() {
-                        return Err("--char must be a letter".to_string());
-                    }
-                    initial_char = Some(c);
-                }
-                arg => {
-                    return Err(format!("Unknown argument: {}", arg));
-                }
-            }
-            i += 1;
-        }
-
-        Ok(Config { anchor, margin, initial_char })
-    }
-}
-
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let config = match Config::from_args() {
+    let config = match Config::load() {
         Ok(c) => c,
         Err(e) => {
             eprintln!("Error: {}", e);
@@ -126,6 +74,18 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     };
 
+    // Comparing two named keymaps doesn't need the compositor
+    if let Some(diff) = config.diff.as_ref().filter(|diff| diff.print_only) {
+        match KeymapDiff::compare(&diff.from, &diff.to, None) {
+            Ok(keymap_diff) => print!("{}", keymap_diff.table()),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+
     eprintln!("Starting kbdviz character reference tool...");
 
     let (mut app, mut event_loop) = App::new(config)?;
@@ -148,10 +108,29 @@ struct App {
 
     layer_surface: Option<LayerSurface>,
     configured: bool,
+    // Logical size last confirmed by the compositor, and the size we asked for
+    size: (u32, u32),
+    requested_size: (u32, u32),
+
+    // HiDPI: fractional scaling needs both protocols; otherwise we fall back
+    // to the integer buffer scale reported through CompositorHandler
+    viewport: Option<WpViewport>,
+    fractional_scale: Option<WpFractionalScaleV1>,
+    scale_factor: f64,
 
     ui: Option<CharRefUI>,
     compose_index: Option<Arc<ComposeIndex>>,  // None until we receive keymap from compositor
-    initial_char: Option<char>,
+    config: Config,
+    // Held modifiers, for modifier-clicks
+    modifiers: Modifiers,
+    // Our own xkb + Compose state, for checking what key presses produce
+    key_state: Option<KeyState>,
+    // Hash of the last keymap string; some compositors send the same one again
+    keymap_hash: Option<u64>,
+    // Comparison shown instead of the search screen, for `kbdviz diff`
+    keymap_diff: Option<KeymapDiff>,
+    // Layout suggestions found on a worker thread: (character, suggestions)
+    suggestion_sender: Sender<(String, u64, Vec<String>)>,
 }
 
 impl App {
@@ -181,7 +160,28 @@ impl App {
         layer_surface.set_margin(config.margin as i32, config.margin as i32, config.margin as i32, config.margin as i32);
         layer_surface.set_exclusive_zone(0);
         layer_surface.set_keyboard_interactivity(KeyboardInteractivity::OnDemand);
-        layer_surface.set_size(WINDOW_WIDTH, WINDOW_HEIGHT);
+        // Start at the default size; once the UI exists it requests a size
+        // that fits its content
+        let initial_size = (
+            config.width.unwrap_or(ui::MIN_WIDTH),
+            config.height.unwrap_or(ui::DEFAULT_HEIGHT),
+        );
+        layer_surface.set_size(initial_size.0, initial_size.1);
+
+        // Fractional scaling (wp_fractional_scale_v1 + wp_viewporter), if available
+        let viewporter = globals.bind::<WpViewporter, _, _>(&qh, 1..=1, ()).ok();
+        let fractional_manager = globals.bind::<WpFractionalScaleManagerV1, _, _>(&qh, 1..=1, ()).ok();
+        let (viewport, fractional_scale) = match (viewporter, fractional_manager) {
+            (Some(viewporter), Some(manager)) => {
+                let surface = layer_surface.wl_surface();
+                let viewport = viewporter.get_viewport(surface, &qh, ());
+                viewport.set_destination(initial_size.0 as i32, initial_size.1 as i32);
+                let fractional = manager.get_fractional_scale(surface, &qh, ());
+                (Some(viewport), Some(fractional))
+            }
+            _ => (None, None),
+        };
+
         layer_surface.commit();
 
         eprintln!("Layer surface created. Press ESC or click to close.");
@@ -191,6 +191,16 @@ impl App {
 
         WaylandSource::new(conn, event_queue).insert(event_loop.handle())?;
 
+        let (suggestion_sender, suggestion_channel) = channel::channel();
+        event_loop
+            .handle()
+            .insert_source(suggestion_channel, |event, _, app: &mut Self| {
+                if let channel::Event::Msg((character, generation, suggestions)) = event {
+                    app.show_suggestions(character, generation, suggestions);
+                }
+            })
+            .map_err(|e| e.error)?;
+
         let app = Self {
             registry_state,
             seat_state,
@@ -201,9 +211,19 @@ impl App {
             loop_signal,
             layer_surface: Some(layer_surface),
             configured: false,
+            size: initial_size,
+            requested_size: initial_size,
+            viewport,
+            fractional_scale,
+            scale_factor: 1.0,
             ui: None,
             compose_index: None,  // Will be populated when we receive keymap
-            initial_char: config.initial_char,
+            config,
+            modifiers: Modifiers::default(),
+            key_state: None,
+            keymap_hash: None,
+            keymap_diff: None,
+            suggestion_sender,
         };
 
         Ok((app, event_loop))
@@ -219,11 +239,226 @@ impl App {
         }
 
         if let (Some(ref layer_surface), Some(ref mut ui)) = (&self.layer_surface, &mut self.ui) {
+            // Ask for a size that fits the content; the compositor confirms it
+            // in a configure event, after which we resize and render again
+            // Sizes fixed in the config file take precedence
+            let (width, height) = ui.preferred_size();
+            let preferred = (
+                self.config.width.unwrap_or(width),
+                self.config.height.unwrap_or(height),
+            );
+            if preferred != self.requested_size {
+                self.requested_size = preferred;
+                layer_surface.set_size(preferred.0, preferred.1);
+            }
+
             ui.render();
             layer_surface.wl_surface().commit();
         }
     }
 
+    /// Copy text to the clipboard using the configured command (wl-copy by default)
+    fn copy_to_clipboard(&self, text: &str) {
+        let (program, args) = self.config.copy_command.split_first().expect("copy command is never empty");
+        if let Err(e) = std::process::Command::new(program)
+            .args(args)
+            .arg(text)
+            .spawn()
+        {
+            eprintln!("Failed to copy to clipboard: {}", e);
+        } else {
+            eprintln!("Copied '{}' to clipboard", text);
+            if self.config.close_on_copy {
+                self.exit();
+            }
+        }
+    }
+
+    /// "1 U+ · 2 HTML · 3 \u{} to copy", from the configured keybindings
+    fn copy_keys_hint(&self) -> String {
+        let keys: Vec<String> = [
+            Representation::Literal,
+            Representation::CodePoint,
+            Representation::HtmlEntity,
+            Representation::RustEscape,
+            Representation::JsonEscape,
+            Representation::Url,
+            Representation::Latex,
+        ]
+        .into_iter()
+        .filter(|&representation| representation != self.config.copy_format)
+        .filter_map(|representation| {
+            let key = self.config.key_name(Action::CopyAs(representation))?;
+            Some(format!("{} {}", key, representation.label()))
+        })
+        .collect();
+
+        let mut hint = if keys.is_empty() {
+            String::new()
+        } else {
+            format!("{} to copy", keys.join(" · "))
+        };
+        if let Some(key) = self.config.key_name(Action::ToggleFavorite) {
+            if !hint.is_empty() {
+                hint.push_str(" · ");
+            }
+            hint.push_str(&format!("{} pin", key));
+        }
+        hint
+    }
+
+    /// Follow the key state: the decoder screen if it is showing, otherwise
+    /// the held-modifier highlighting of the result rows
+    fn refresh_key_state(&mut self) {
+        let (Some(ui), Some(state)) = (self.ui.as_mut(), self.key_state.as_ref()) else {
+            return;
+        };
+        if ui.is_decoding() {
+            ui.set_decoder(Some(state.decode()));
+            self.render();
+        } else if ui.set_held_modifiers(state.held_modifiers()) {
+            self.render();
+        }
+    }
+
+    /// Copy format for a click with the currently held modifiers
+    fn click_format(&self) -> Representation {
+        if self.modifiers.shift {
+            self.config.shift_click_format
+        } else if self.modifiers.ctrl {
+            self.config.ctrl_click_format
+        } else if self.modifiers.alt {
+            self.config.alt_click_format
+        } else {
+            self.config.copy_format
+        }
+    }
+
+    fn handle_action(&mut self, action: Action) {
+        if action == Action::Close {
+            eprintln!("Close key pressed, closing...");
+            self.exit();
+            return;
+        }
+
+        let Some(ref mut ui) = self.ui else {
+            return;
+        };
+        match action {
+            Action::Clear => ui.erase(),
+            Action::SelectNext => ui.move_selection(1),
+            Action::SelectPrev => ui.move_selection(-1),
+            Action::Copy | Action::CopyAs(_) => {
+                let representation = match action {
+                    Action::CopyAs(representation) => representation,
+                    _ => self.config.copy_format,
+                };
+                if let Some(text) = ui.copy_selected(representation) {
+                    self.copy_to_clipboard(&text);
+                }
+            }
+            Action::ToggleFavorite => ui.toggle_favorite(),
+            Action::Forget => ui.forget_selected(),
+            Action::Decode => {
+                if let Some(state) = &mut self.key_state {
+                    state.reset_compose();
+                    ui.set_decoder(Some(state.decode()));
+                }
+            }
+            Action::Drill => {
+                if let Some(state) = &mut self.key_state {
+                    state.reset_compose();
+                }
+                if !ui.start_drill() {
+                    eprintln!("Nothing to practice");
+                }
+            }
+            Action::AddComposeRule => {
+                if let Err(e) = ui.propose_compose_rule() {
+                    eprintln!("{}", e);
+                    ui.set_notice(e);
+                }
+            }
+            Action::SuggestLayouts => {
+                let layout = self.key_state.as_ref().map(KeyState::layout_name).unwrap_or_default();
+                match ui.start_suggestions() {
+                    Ok((character, generation)) => {
+                        // Compiling the candidate keymaps takes a while
+                        let sender = self.suggestion_sender.clone();
+                        std::thread::spawn(move || {
+                            let suggestions = suggest::suggest(&layout, &character);
+                            let _ = sender.send((character, generation, suggestions));
+                        });
+                    }
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        ui.set_notice(e);
+                    }
+                }
+            }
+            Action::Close => unreachable!(),
+        }
+        self.render();
+    }
+
+    /// Apply a new output scale factor to the surface and the UI
+    fn set_scale_factor(&mut self, scale: f64) {
+        if (self.scale_factor - scale).abs() < f64::EPSILON {
+            return;
+        }
+        eprintln!("Scale factor changed: {}", scale);
+        self.scale_factor = scale;
+
+        if let Some(ref layer_surface) = self.layer_surface {
+            // With a viewport the compositor maps our physical-size buffer onto
+            // the logical destination size; otherwise use the integer buffer scale
+            if self.viewport.is_none() {
+                layer_surface.wl_surface().set_buffer_scale(scale as i32);
+            }
+        }
+
+        if let Some(ref mut ui) = self.ui {
+            ui.set_scale(scale);
+        }
+        self.render();
+    }
+
+    /// Show the layouts found by a background search; the UI drops them if
+    /// the layout changed since the search started
+    fn show_suggestions(&mut self, character: String, generation: u64, suggestions: Vec<String>) {
+        for suggestion in &suggestions {
+            eprintln!("'{}': {}", character, suggestion);
+        }
+        if suggestions.is_empty() {
+            eprintln!("No layout or option found for '{}'", character);
+        }
+        if let Some(ui) = &mut self.ui {
+            ui.set_suggestions(character, generation, suggestions);
+        }
+        self.render();
+    }
+
+    /// Show a new keymap's index in the running UI, after a layout switch
+    /// or keyboard hotplug
+    fn swap_compose_index(&mut self, index: Arc<ComposeIndex>) {
+        let layout = self.key_state.as_ref().map(KeyState::layout_name).unwrap_or_default();
+        let Some(ui) = self.ui.as_mut() else {
+            return;
+        };
+        ui.set_compose_index(index);
+        if self.config.diff.is_some() {
+            ui.set_diff(self.keymap_diff.clone());
+        }
+        // The cards' hints are for the old layout
+        if ui.is_drilling() {
+            ui.stop_drill();
+        }
+        eprintln!("Layout changed: {}", layout);
+        ui.set_notice(format!("Layout changed: {}", layout));
+        self.render();
+        self.refresh_key_state();
+    }
+
     /// Try to create the UI - requires both surface configured and keymap received
     fn try_create_ui(&mut self) {
         if !self.configured || self.compose_index.is_none() || self.ui.is_some() {
@@ -235,15 +470,29 @@ impl App {
             // Get the configured size from the layer surface
             let mut ui = CharRefUI::new(
                 surface,
-                WINDOW_WIDTH,
-                WINDOW_HEIGHT,
+                self.size.0,
+                self.size.1,
                 &self.shm,
                 self.compose_index.clone().unwrap(),
+                self.config.appearance.clone(),
+                History::load(),
             );
+            ui.set_scale(self.scale_factor);
+            ui.set_detail_hint(self.copy_keys_hint());
+            let mut rule_hint = "No route on this layout".to_string();
+            if let Some(key) = self.config.key_name(Action::AddComposeRule) {
+                rule_hint.push_str(&format!(" · {} adds a Compose rule", key));
+            }
+            if let Some(key) = self.config.key_name(Action::SuggestLayouts) {
+                rule_hint.push_str(&format!(" · {} finds layouts", key));
+            }
+            ui.set_rule_hint(rule_hint);
+            ui.set_languages(self.config.languages.clone().unwrap_or_default());
             // Apply initial filter if specified via --char
-            if let Some(c) = self.initial_char {
+            if let Some(c) = self.config.initial_char {
                 ui.set_filter(c);
             }
+            ui.set_diff(self.keymap_diff.clone());
             self.ui = Some(ui);
             self.render();
         }
@@ -251,7 +500,12 @@ impl App {
 }
 
 impl CompositorHandler for App {
-    fn scale_factor_changed(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_surface::WlSurface, _: i32) {}
+    fn scale_factor_changed(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_surface::WlSurface, new_factor: i32) {
+        // The preferred fractional scale takes precedence when supported
+        if self.fractional_scale.is_none() {
+            self.set_scale_factor(new_factor as f64);
+        }
+    }
     fn frame(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_surface::WlSurface, _: u32) {}
     fn transform_changed(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_surface::WlSurface, _: wl_output::Transform) {}
     fn surface_enter(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_surface::WlSurface, _: &wl_output::WlOutput) {}
@@ -273,10 +527,25 @@ impl LayerShellHandler for App {
     }
 
     fn configure(&mut self, _: &Connection, _qh: &QueueHandle<Self>, _: &LayerSurface, configure: LayerSurfaceConfigure, _: u32) {
+        // A zero dimension means the compositor leaves that dimension up to us
+        let (new_width, new_height) = configure.new_size;
+        let width = if new_width == 0 { self.requested_size.0 } else { new_width };
+        let height = if new_height == 0 { self.requested_size.1 } else { new_height };
+        self.size = (width, height);
+
+        if let Some(ref viewport) = self.viewport {
+            viewport.set_destination(width as i32, height as i32);
+        }
+        if let Some(ref mut ui) = self.ui {
+            ui.resize(width, height);
+        }
+
         if !self.configured {
-            eprintln!("Layer surface configured: {}x{}", configure.new_size.0, configure.new_size.1);
+            eprintln!("Layer surface configured: {}x{}", width, height);
             self.configured = true;
             self.try_create_ui();
+        } else {
+            self.render();
         }
     }
 }
@@ -299,22 +568,58 @@ impl SeatHandler for App {
 }
 
 impl KeyboardHandler for App {
-    fn enter(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_keyboard::WlKeyboard, _: &wl_surface::WlSurface, _: u32, _: &[u32], _: &[xkbcommon::xkb::Keysym]) {}
-    fn leave(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_keyboard::WlKeyboard, _: &wl_surface::WlSurface, _: u32) {}
+    fn enter(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_keyboard::WlKeyboard, _: &wl_surface::WlSurface, _: u32, raw: &[u32], _: &[xkbcommon::xkb::Keysym]) {
+        if let Some(state) = &mut self.key_state {
+            state.enter(raw);
+        }
+    }
+
+    fn leave(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_keyboard::WlKeyboard, _: &wl_surface::WlSurface, _: u32) {
+        if let Some(state) = &mut self.key_state {
+            state.leave();
+        }
+    }
 
     fn update_keymap(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_keyboard::WlKeyboard, keymap: Keymap<'_>) {
         eprintln!("Received keymap from compositor");
 
         // Get the keymap as a string and create our XkbKeymap
         let keymap_string = keymap.as_string();
+        let keymap_hash = cache::hash(keymap_string.as_bytes());
+        if self.keymap_hash == Some(keymap_hash) {
+            eprintln!("Keymap unchanged");
+            return;
+        }
+        self.keymap_hash = Some(keymap_hash);
         match XkbKeymap::from_string(&keymap_string) {
             Ok(xkb_keymap) => {
+                self.key_state = Some(KeyState::new(&xkb_keymap));
                 // Build the compose index from the actual keymap
-                match ComposeIndex::build(&xkb_keymap) {
+                match cache::index_for(&xkb_keymap) {
                     Ok(index) => {
                         eprintln!("Loaded {} base characters with variants", index.count());
-                        self.compose_index = Some(Arc::new(index));
-                        self.try_create_ui();
+                        if let Some(diff) = &self.config.diff {
+                            match KeymapDiff::compare(&diff.from, &diff.to, Some(&index)) {
+                                Ok(keymap_diff) => {
+                                    print!("{}", keymap_diff.table());
+                                    self.keymap_diff = Some(keymap_diff);
+                                }
+                                // Keep showing the last comparison after a layout change
+                                Err(e) if self.ui.is_some() => eprintln!("Failed to compare keymaps: {}", e),
+                                Err(e) => {
+                                    eprintln!("Error: {}", e);
+                                    self.exit();
+                                    return;
+                                }
+                            }
+                        }
+                        let index = Arc::new(index);
+                        self.compose_index = Some(index.clone());
+                        if self.ui.is_some() {
+                            self.swap_compose_index(index);
+                        } else {
+                            self.try_create_ui();
+                        }
                     }
                     Err(e) => eprintln!("Failed to build compose index: {}", e),
                 }
@@ -325,14 +630,63 @@ impl KeyboardHandler for App {
 
     fn press_key(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_keyboard::WlKeyboard, _: u32, event: KeyEvent) {
         eprintln!("Key pressed: code={}, keysym={:?}", event.raw_code, event.keysym);
+        let output = self.key_state.as_mut().map(|state| state.press(event.raw_code));
 
-        // ESC to exit (keycode 1 on this system!)
-        if event.raw_code == 1 {  // ESC
-            eprintln!("ESC pressed, closing...");
-            self.exit();
+        // The decoder shows every key; Close leaves it
+        if self.ui.as_ref().is_some_and(|ui| ui.is_decoding()) {
+            if self.config.action_for(event.keysym) == Some(Action::Close) {
+                if let Some(ui) = self.ui.as_mut() {
+                    ui.set_decoder(None);
+                }
+                self.render();
+            } else {
+                self.refresh_key_state();
+            }
+            return;
+        }
+
+        // While practicing, every key but Close is an answer
+        if let (Some(ui), Some(output)) = (self.ui.as_mut(), output) {
+            if ui.is_drilling() {
+                if self.config.action_for(event.keysym) == Some(Action::Close) {
+                    ui.stop_drill();
+                    self.render();
+                } else if ui.drill_key(output) {
+                    self.render();
+                }
+                return;
+            }
+        }
+
+        // A proposed Compose rule waits for Copy (Return) or Close
+        if let Some(ui) = self.ui.as_mut().filter(|ui| ui.is_proposing_rule()) {
+            match self.config.action_for(event.keysym) {
+                Some(Action::Copy) => ui.finish_compose_rule(true),
+                Some(Action::Close) => ui.finish_compose_rule(false),
+                _ => return,
+            }
+            self.render();
             return;
         }
 
+        // The comparison only scrolls; Close quits as usual
+        if self.ui.as_ref().is_some_and(|ui| ui.is_showing_diff()) {
+            if let Some(action @ (Action::SelectNext | Action::SelectPrev | Action::Close)) = self.config.action_for(event.keysym) {
+                self.handle_action(action);
+            }
+            return;
+        }
+
+        // Bound actions (ESC to close, etc.) take precedence over text input,
+        // except that a name search can contain digits and "*"
+        if let Some(action) = self.config.action_for(event.keysym) {
+            let searching = self.ui.as_ref().is_some_and(|ui| ui.is_searching_names());
+            if !(searching && matches!(action, Action::CopyAs(_) | Action::ToggleFavorite)) {
+                self.handle_action(action);
+                return;
+            }
+        }
+
         // Handle text input
         if let Some(ref mut ui) = self.ui {
             ui.handle_key_press(event.raw_code, event.keysym);
@@ -340,31 +694,33 @@ impl KeyboardHandler for App {
         }
     }
 
-    fn release_key(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_keyboard::WlKeyboard, _: u32, _: KeyEvent) {}
-    fn update_modifiers(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_keyboard::WlKeyboard, _: u32, _: Modifiers, _: u32) {}
+    fn release_key(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_keyboard::WlKeyboard, _: u32, event: KeyEvent) {
+        if let Some(state) = &mut self.key_state {
+            state.release(event.raw_code);
+        }
+        self.refresh_key_state();
+    }
+
+    fn update_modifiers(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_keyboard::WlKeyboard, _: u32, modifiers: Modifiers, layout: u32) {
+        self.modifiers = modifiers;
+        if let Some(state) = &mut self.key_state {
+            state.sync_modifiers(modifiers.caps_lock, modifiers.num_lock, layout);
+        }
+        self.refresh_key_state();
+    }
 }
 
 impl PointerHandler for App {
     fn pointer_frame(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_pointer::WlPointer, events: &[PointerEvent]) {
         for event in events {
             match event.kind {
-                PointerEventKind::Press { button, .. } => {
-                    // Left mouse button = 272 (BTN_LEFT)
-                    if button == 272 {
-                        if let Some(ref mut ui) = self.ui {
-                            if let Some(character) = ui.handle_click(event.position.0, event.position.1) {
-                                // Copy to clipboard using wl-copy
-                                if let Err(e) = std::process::Command::new("wl-copy")
-                                    .arg(&character)
-                                    .spawn()
-                                {
-                                    eprintln!("Failed to copy to clipboard: {}", e);
-                                } else {
-                                    eprintln!("Copied '{}' to clipboard", character);
-                                }
-                                self.render();
-                            }
-                        }
+                // Left mouse button = 272 (BTN_LEFT)
+                PointerEventKind::Press { button: 272, .. } => {
+                    let representation = self.click_format();
+                    let clicked = self.ui.as_mut().and_then(|ui| ui.handle_click(event.position.0, event.position.1));
+                    if let Some(character) = clicked {
+                        self.copy_to_clipboard(&representation.format(&character));
+                        self.render();
                     }
                 }
                 PointerEventKind::Motion { .. } => {
@@ -403,3 +759,15 @@ delegate_pointer!(App);
 delegate_layer!(App);
 delegate_shm!(App);
 delegate_registry!(App);
+delegate_noop!(App: WpViewporter);
+delegate_noop!(App: WpViewport);
+delegate_noop!(App: WpFractionalScaleManagerV1);
+
+impl Dispatch<WpFractionalScaleV1, ()> for App {
+    fn event(app: &mut Self, _: &WpFractionalScaleV1, event: wp_fractional_scale_v1::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        if let wp_fractional_scale_v1::Event::PreferredScale { scale } = event {
+            // The scale is sent as a fraction with a denominator of 120
+            app.set_scale_factor(scale as f64 / 120.0);
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use crate::compose::ComposeEntry;
+use crate::history::History;
+use std::env;
+
+/// Accented letters per language, most frequent first (lowercase only)
+const LANGUAGES: &[(&str, &str)] = &[
+    ("ca", "àèéíòóúïüç"),
+    ("cs", "ěáíéýřčšůžúťďň"),
+    ("da", "øåæé"),
+    ("de", "äüöß"),
+    ("el", "άέίόύήώϊϋΐΰ"),
+    ("eo", "ĉĝĥĵŝŭ"),
+    ("es", "éñóíáúü"),
+    ("et", "äõöüšž"),
+    ("fi", "äö"),
+    ("fr", "éèàçêùâîôëûïœ"),
+    ("hu", "éáőöóüíúű"),
+    ("is", "áðéíóúýþæö"),
+    ("it", "àèéìòù"),
+    ("lt", "ąčęėįšųūž"),
+    ("lv", "āēīūčšžģķļņ"),
+    ("nb", "øåæé"),
+    ("nl", "ëéïèöü"),
+    ("pl", "ęąóśłżćńź"),
+    ("pt", "ãçéáêóíõúâôà"),
+    ("ro", "ăâîșțşţ"),
+    ("ru", "ёй"),
+    ("sk", "áíéýčšžľúôťďňäŕĺó"),
+    ("sv", "äåö"),
+    ("tr", "ıçşğöü"),
+    ("uk", "їй"),
+];
+
+// Relative weights of the ranking signals
+const COPY_WEIGHT: i64 = 40;
+const PREFERRED_LANGUAGE_WEIGHT: i64 = 10;
+const STEP_PENALTY: i64 = 4;
+
+/// Whether a language has a bundled frequency table
+pub fn is_known_language(code: &str) -> bool {
+    LANGUAGES.iter().any(|(language, _)| *language == code)
+}
+
+pub fn known_languages() -> Vec<&'static str> {
+    LANGUAGES.iter().map(|(language, _)| *language).collect()
+}
+
+/// Languages from the locale (LANGUAGE, LC_ALL, LC_MESSAGES, LANG), e.g.
+/// "nl_BE.UTF-8" → "nl". Only languages with a frequency table are kept.
+pub fn locale_languages() -> Vec<String> {
+    let mut languages = Vec::new();
+    for var in ["LANGUAGE", "LC_ALL", "LC_MESSAGES", "LANG"] {
+        let Ok(value) = env::var(var) else {
+            continue;
+        };
+        for locale in value.split(':') {
+            let code = locale.split(['_', '.', '@']).next().unwrap_or("");
+            if is_known_language(code) && !languages.iter().any(|l| l == code) {
+                languages.push(code.to_string());
+            }
+        }
+    }
+    languages
+}
+
+/// Sort variants so the ones the user is most likely to want come first:
+/// characters they copy often, common letters in their preferred languages
+/// (then in any language), and routes with fewer steps
+pub fn rank(entries: &mut [ComposeEntry], history: &History, preferred: &[String]) {
+    // Stable sort keeps keycode order between equal scores
+    entries.sort_by_cached_key(|entry| -score(entry, history, preferred));
+}
+
+fn score(entry: &ComposeEntry, history: &History, preferred: &[String]) -> i64 {
+    let copies = i64::from(history.copy_count(&entry.character).min(25));
+    let steps = entry.key_sequence.split("  ").count() as i64;
+
+    let Some(c) = entry.character.chars().next().and_then(|c| c.to_lowercase().next()) else {
+        return 0;
+    };
+    let mut frequency = 0;
+    for (language, letters) in LANGUAGES {
+        let Some(position) = letters.chars().position(|l| l == c) else {
+            continue;
+        };
+        let weight = (letters.chars().count() - position) as i64;
+        // Earlier preferences count more
+        frequency += match preferred.iter().position(|p| p == language) {
+            Some(rank) => weight * PREFERRED_LANGUAGE_WEIGHT * (preferred.len() - rank) as i64,
+            None => weight,
+        };
+    }
+
+    copies * COPY_WEIGHT + frequency - steps * STEP_PENALTY
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranked(characters: &[&str], history: &History, preferred: &[&str]) -> Vec<String> {
+        let mut entries: Vec<ComposeEntry> = characters
+            .iter()
+            .map(|c| ComposeEntry { character: c.to_string(), key_sequence: "AltGr-x".to_string(), alternatives: Vec::new() })
+            .collect();
+        let preferred: Vec<String> = preferred.iter().map(|p| p.to_string()).collect();
+        rank(&mut entries, history, &preferred);
+        entries.into_iter().map(|entry| entry.character).collect()
+    }
+
+    #[test]
+    fn common_letters_come_first() {
+        assert_eq!(ranked(&["ŭ", "é"], &History::in_memory(), &[]), ["é", "ŭ"]);
+    }
+
+    #[test]
+    fn preferred_language_reorders() {
+        let history = History::in_memory();
+        assert_eq!(ranked(&["ñ", "ç"], &history, &[]), ["ç", "ñ"]);
+        assert_eq!(ranked(&["ñ", "ç"], &history, &["es"]), ["ñ", "ç"]);
+    }
+
+    #[test]
+    fn copy_history_beats_language_frequency() {
+        let mut history = History::in_memory();
+        for _ in 0..3 {
+            history.record_copy("ŭ");
+        }
+        assert_eq!(ranked(&["é", "ŭ"], &history, &[]), ["ŭ", "é"]);
+    }
+
+    #[test]
+    fn fewer_steps_break_ties() {
+        let mut entries = vec![
+            ComposeEntry { character: "é".to_string(), key_sequence: "Multi_key  '  e".to_string(), alternatives: Vec::new() },
+            ComposeEntry { character: "é".to_string(), key_sequence: "AltGr-e".to_string(), alternatives: Vec::new() },
+        ];
+        rank(&mut entries, &History::in_memory(), &[]);
+        assert_eq!(entries[0].key_sequence, "AltGr-e");
+    }
+}
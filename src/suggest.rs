@@ -0,0 +1,179 @@
+use crate::compose::ComposeIndex;
+use crate::keyboard::XkbKeymap;
+use std::env;
+use std::path::PathBuf;
+
+// Layouts made for typing many languages, tried after the variants of the
+// current layout
+const MULTILINGUAL_LAYOUTS: &[(&str, &str)] = &[("us", "altgr-intl"), ("us", "intl"), ("gb", "extd"), ("gb", "intl")];
+
+// Compose key options, in order of preference. Right Alt is the usual
+// choice, but on layouts with AltGr it takes that key away.
+const COMPOSE_OPTIONS: &[&str] = &["compose:ralt", "compose:menu"];
+
+// Stop after this many suggestions; each one means compiling a keymap
+const MAX_SUGGESTIONS: usize = 4;
+
+// Layouts tried at most, so a layout with dozens of variants stays quick
+const MAX_CANDIDATES: usize = 12;
+
+/// Layout and variant descriptions from xkeyboard-config's evdev.lst
+struct Rules {
+    // (layout, description)
+    layouts: Vec<(String, String)>,
+    // (layout, variant, description)
+    variants: Vec<(String, String, String)>,
+}
+
+/// Ways to get a character the current layout can't type: other variants
+/// of the layout, multilingual layouts, or a Compose key. Each candidate is
+/// compiled offline and indexed like the real keymap, so the routes shown
+/// are the ones that would work, e.g. "available in us(intl) as AltGr-e".
+/// `current_layout` is the description xkb reports ("English (US)").
+/// Compiling the candidates takes a while, so callers run this on a thread.
+pub fn suggest(current_layout: &str, character: &str) -> Vec<String> {
+    let Some(rules) = Rules::load() else {
+        return Vec::new();
+    };
+    let current = rules.find(current_layout);
+
+    let mut candidates: Vec<(String, String)> = Vec::new();
+    if let Some((layout, variant)) = &current {
+        // Leave room for the multilingual layouts when there are many variants
+        candidates.extend(
+            rules
+                .variants
+                .iter()
+                .filter(|(l, v, _)| l == layout && v != variant)
+                .map(|(l, v, _)| (l.clone(), v.clone()))
+                .take(MAX_CANDIDATES - MULTILINGUAL_LAYOUTS.len()),
+        );
+    }
+    for (layout, variant) in MULTILINGUAL_LAYOUTS {
+        let candidate = (layout.to_string(), variant.to_string());
+        if !candidates.contains(&candidate) && current.as_ref() != Some(&candidate) {
+            candidates.push(candidate);
+        }
+    }
+
+    let mut suggestions = Vec::new();
+    // A Compose key on the current layout is the least disruptive change
+    if let Some((layout, variant)) = &current {
+        let compose = COMPOSE_OPTIONS.iter().find_map(|option| {
+            route_in(layout, variant, option, character)
+                .filter(|route| !route.contains("AltGr"))
+                .map(|route| format!("enable {}, then {}", option, route))
+        });
+        suggestions.extend(compose);
+    }
+    for (layout, variant) in candidates {
+        if suggestions.len() >= MAX_SUGGESTIONS {
+            break;
+        }
+        if let Some(route) = route_in(&layout, &variant, "", character) {
+            let name = if variant.is_empty() { layout } else { format!("{}({})", layout, variant) };
+            suggestions.push(format!("available in {} as {}", name, route));
+        }
+    }
+    suggestions
+}
+
+/// The shortest route to a character on a keymap compiled from names.
+/// Not cached: these keymaps would crowd the user's own out of the cache.
+fn route_in(layout: &str, variant: &str, options: &str, character: &str) -> Option<String> {
+    let keymap = XkbKeymap::from_names(layout, variant, options).ok()?;
+    let index = ComposeIndex::build(&keymap).ok()?;
+    let entry = index.entry_for(character)?;
+    Some(entry.key_sequence.replace("  ", " "))
+}
+
+impl Rules {
+    /// Read $XKB_CONFIG_ROOT/rules/evdev.lst (usually /usr/share/X11/xkb)
+    fn load() -> Option<Self> {
+        let root = env::var_os("XKB_CONFIG_ROOT")
+            .filter(|v| !v.is_empty())
+            .map_or_else(|| PathBuf::from("/usr/share/X11/xkb"), PathBuf::from);
+        let path = root.join("rules").join("evdev.lst");
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        Some(Self::parse(&contents))
+    }
+
+    /// The layout and variant sections of an evdev.lst
+    fn parse(contents: &str) -> Self {
+        let mut rules = Rules { layouts: Vec::new(), variants: Vec::new() };
+        let mut section = "";
+        for line in contents.lines() {
+            if let Some(name) = line.strip_prefix("! ") {
+                section = if name == "layout" { "layout" } else if name == "variant" { "variant" } else { "" };
+                continue;
+            }
+            let Some((name, description)) = line.trim().split_once(char::is_whitespace) else {
+                continue;
+            };
+            let description = description.trim();
+            match section {
+                "layout" => rules.layouts.push((name.to_string(), description.to_string())),
+                // "intl            us: English (US, intl., with dead keys)"
+                "variant" => {
+                    if let Some((layout, description)) = description.split_once(": ") {
+                        rules.variants.push((layout.to_string(), name.to_string(), description.to_string()));
+                    }
+                }
+                _ => {}
+            }
+        }
+        rules
+    }
+
+    /// (layout, variant) names for a layout description
+    fn find(&self, description: &str) -> Option<(String, String)> {
+        if let Some((layout, _)) = self.layouts.iter().find(|(_, d)| d == description) {
+            return Some((layout.clone(), String::new()));
+        }
+        self.variants
+            .iter()
+            .find(|(_, _, d)| d == description)
+            .map(|(layout, variant, _)| (layout.clone(), variant.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EVDEV_LST: &str = "\
+! model
+  pc105           Generic 105-key PC
+
+! layout
+  us              English (US)
+  de              German
+
+! variant
+  intl            us: English (US, intl., with dead keys)
+  altgr-intl      us: English (intl., with AltGr dead keys)
+  nodeadkeys      de: German (no dead keys)
+
+! option
+  compose:ralt         Right Alt
+";
+
+    #[test]
+    fn parses_layouts_and_variants() {
+        let rules = Rules::parse(EVDEV_LST);
+        assert_eq!(rules.layouts, [("us".to_string(), "English (US)".to_string()), ("de".to_string(), "German".to_string())]);
+        assert_eq!(rules.variants.len(), 3);
+        assert_eq!(rules.find("English (US)"), Some(("us".to_string(), String::new())));
+        assert_eq!(rules.find("English (US, intl., with dead keys)"), Some(("us".to_string(), "intl".to_string())));
+        assert_eq!(rules.find("German (no dead keys)"), Some(("de".to_string(), "nodeadkeys".to_string())));
+        // Models and options aren't layouts
+        assert_eq!(rules.find("Generic 105-key PC"), None);
+        assert_eq!(rules.find("Right Alt"), None);
+    }
+}
@@ -0,0 +1,329 @@
+use std::env;
+use std::path::PathBuf;
+use tiny_skia::ColorU8;
+
+/// Colors used by the renderer
+#[derive(Clone)]
+pub struct Theme {
+    pub name: String,
+    pub background: ColorU8,
+    pub text_primary: ColorU8,
+    pub text_secondary: ColorU8,
+    pub text_tertiary: ColorU8,
+    pub accent: ColorU8,            // Bright target key color
+    // Keycap styling colors
+    pub keycap_bg: ColorU8,
+    pub keycap_border: ColorU8,
+    pub modifier_keycap_bg: ColorU8,
+    pub modifier_text: ColorU8,     // Dimmer modifier text
+    pub connector: ColorU8,         // Brighter + and → symbols
+    pub divider: ColorU8,
+    pub hover_highlight: ColorU8,
+    pub copied_highlight: ColorU8,
+}
+
+fn rgb(r: u8, g: u8, b: u8) -> ColorU8 {
+    ColorU8::from_rgba(r, g, b, 255)
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Theme {
+            name: "dark".to_string(),
+            background: rgb(28, 28, 33),
+            text_primary: rgb(255, 255, 255),
+            text_secondary: rgb(191, 191, 198),
+            text_tertiary: rgb(127, 127, 140),
+            accent: rgb(165, 216, 255),
+            keycap_bg: rgb(48, 48, 54),
+            keycap_border: rgb(70, 70, 78),
+            modifier_keycap_bg: rgb(34, 34, 40),
+            modifier_text: rgb(153, 153, 165),
+            connector: rgb(165, 165, 178),
+            divider: rgb(50, 50, 55),
+            hover_highlight: rgb(35, 35, 40),
+            copied_highlight: rgb(42, 42, 48),
+        }
+    }
+
+    pub fn light() -> Self {
+        Theme {
+            name: "light".to_string(),
+            background: rgb(246, 246, 248),
+            text_primary: rgb(20, 20, 24),
+            text_secondary: rgb(70, 70, 80),
+            text_tertiary: rgb(125, 125, 135),
+            accent: rgb(0, 95, 184),
+            keycap_bg: rgb(255, 255, 255),
+            keycap_border: rgb(196, 196, 204),
+            modifier_keycap_bg: rgb(232, 232, 238),
+            modifier_text: rgb(90, 90, 100),
+            connector: rgb(110, 110, 120),
+            divider: rgb(220, 220, 226),
+            hover_highlight: rgb(236, 236, 241),
+            copied_highlight: rgb(222, 229, 242),
+        }
+    }
+
+    pub fn high_contrast() -> Self {
+        Theme {
+            name: "high-contrast".to_string(),
+            background: rgb(0, 0, 0),
+            text_primary: rgb(255, 255, 255),
+            text_secondary: rgb(255, 255, 255),
+            text_tertiary: rgb(210, 210, 210),
+            accent: rgb(255, 255, 0),
+            keycap_bg: rgb(0, 0, 0),
+            keycap_border: rgb(255, 255, 255),
+            modifier_keycap_bg: rgb(0, 0, 0),
+            modifier_text: rgb(255, 255, 255),
+            connector: rgb(255, 255, 255),
+            divider: rgb(255, 255, 255),
+            hover_highlight: rgb(30, 30, 110),
+            copied_highlight: rgb(0, 80, 0),
+        }
+    }
+
+    fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Theme::dark()),
+            "light" => Some(Theme::light()),
+            "high-contrast" => Some(Theme::high_contrast()),
+            _ => None,
+        }
+    }
+
+    /// Resolve a theme by name: "auto", a built-in theme, or a user theme
+    /// from $XDG_CONFIG_HOME/kbdviz/themes/<name>.toml
+    pub fn load(name: &str) -> Result<Self, String> {
+        if name == "auto" {
+            let scheme = detect_color_scheme();
+            eprintln!("Desktop color scheme: {:?}", scheme);
+            return Ok(match scheme {
+                ColorScheme::HighContrast => Theme::high_contrast(),
+                ColorScheme::Light => Theme::light(),
+                ColorScheme::Dark | ColorScheme::NoPreference => Theme::dark(),
+            });
+        }
+
+        if let Some(theme) = Theme::builtin(name) {
+            return Ok(theme);
+        }
+
+        let path = themes_dir()
+            .map(|dir| dir.join(format!("{}.toml", name)))
+            .filter(|path| path.exists())
+            .ok_or_else(|| format!("Unknown theme: {} (not built in and no themes/{}.toml)", name, name))?;
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        let table: toml::Table = contents
+            .parse()
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        Theme::from_table(name, &table).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// A user theme: an optional `base` built-in theme plus color overrides
+    fn from_table(name: &str, table: &toml::Table) -> Result<Self, String> {
+        let mut theme = match table.get("base") {
+            Some(base) => {
+                let base = base.as_str().ok_or("base: expected a string")?;
+                Theme::builtin(base).ok_or_else(|| format!("base: unknown built-in theme \"{}\"", base))?
+            }
+            None => Theme::dark(),
+        };
+        theme.name = name.to_string();
+
+        for (key, value) in table {
+            if key == "base" {
+                continue;
+            }
+            let value = value.as_str().ok_or_else(|| format!("{}: expected a string", key))?;
+            let color = parse_color(value).map_err(|e| format!("{}: {}", key, e))?;
+            theme.set_color(key, color).ok_or_else(|| format!("{}: unknown color name", key))?;
+        }
+
+        Ok(theme)
+    }
+
+    /// Set a color by its config name, returns None for unknown names
+    pub fn set_color(&mut self, name: &str, color: ColorU8) -> Option<()> {
+        let slot = match name {
+            "background" => &mut self.background,
+            "text" => &mut self.text_primary,
+            "text_secondary" => &mut self.text_secondary,
+            "text_tertiary" => &mut self.text_tertiary,
+            "accent" => &mut self.accent,
+            "keycap" => &mut self.keycap_bg,
+            "keycap_border" => &mut self.keycap_border,
+            "modifier_keycap" => &mut self.modifier_keycap_bg,
+            "modifier_text" => &mut self.modifier_text,
+            "connector" => &mut self.connector,
+            "divider" => &mut self.divider,
+            "hover" => &mut self.hover_highlight,
+            "copied" => &mut self.copied_highlight,
+            _ => return None,
+        };
+        *slot = color;
+        Some(())
+    }
+}
+
+/// Parse "#rrggbb" or "#rrggbbaa"
+pub fn parse_color(value: &str) -> Result<ColorU8, String> {
+    let hex = value
+        .strip_prefix('#')
+        .filter(|h| (h.len() == 6 || h.len() == 8) && h.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or_else(|| format!("expected a color like \"#a6d9ff\", got \"{}\"", value))?;
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+    let alpha = if hex.len() == 8 { channel(6) } else { 255 };
+    Ok(ColorU8::from_rgba(channel(0), channel(2), channel(4), alpha))
+}
+
+fn themes_dir() -> Option<PathBuf> {
+    config_home().map(|dir| dir.join("kbdviz").join("themes"))
+}
+
+/// $XDG_CONFIG_HOME, falling back to ~/.config
+pub fn config_home() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+}
+
+/// Values of the `org.freedesktop.appearance color-scheme` portal setting,
+/// plus the accessibility high-contrast switch
+#[derive(Debug, PartialEq, Eq)]
+enum ColorScheme {
+    NoPreference,
+    Dark,
+    Light,
+    HighContrast,
+}
+
+/// Detect the desktop color scheme without a D-Bus connection.
+///
+/// The portal's `color-scheme` (0 = no preference, 1 = prefer dark,
+/// 2 = prefer light) is backed by the GSettings key
+/// `org.gnome.desktop.interface color-scheme`, which the keyfile backend
+/// stores in $XDG_CONFIG_HOME/glib-2.0/settings/keyfile. KBDVIZ_COLOR_SCHEME
+/// (`dark`, `light`, `high-contrast`, or the portal's numeric values)
+/// overrides the detection.
+fn detect_color_scheme() -> ColorScheme {
+    let keyfile = config_home()
+        .map(|dir| dir.join("glib-2.0").join("settings").join("keyfile"))
+        .and_then(|path| std::fs::read_to_string(path).ok());
+    color_scheme_from(
+        env::var("KBDVIZ_COLOR_SCHEME").ok().as_deref(),
+        keyfile.as_deref(),
+        env::var("GTK_THEME").ok().as_deref(),
+    )
+}
+
+/// The first source that says anything: the override, the keyfile, then
+/// GTK_THEME
+fn color_scheme_from(override_value: Option<&str>, keyfile: Option<&str>, gtk_theme: Option<&str>) -> ColorScheme {
+    if let Some(value) = override_value {
+        return match value.trim() {
+            "1" | "dark" | "prefer-dark" => ColorScheme::Dark,
+            "2" | "light" | "prefer-light" => ColorScheme::Light,
+            "high-contrast" => ColorScheme::HighContrast,
+            _ => ColorScheme::NoPreference,
+        };
+    }
+
+    if let Some(keyfile) = keyfile {
+        if keyfile_value(keyfile, "org/gnome/desktop/a11y/interface", "high-contrast") == Some("true") {
+            return ColorScheme::HighContrast;
+        }
+        match keyfile_value(keyfile, "org/gnome/desktop/interface", "color-scheme") {
+            Some("'prefer-dark'") => return ColorScheme::Dark,
+            Some("'prefer-light'") => return ColorScheme::Light,
+            Some(_) => return ColorScheme::NoPreference,
+            None => {}
+        }
+    }
+
+    // GTK_THEME=Adwaita:dark is a common way to force dark mode
+    match gtk_theme {
+        Some(theme) if theme.ends_with(":dark") => ColorScheme::Dark,
+        Some(theme) if theme.ends_with(":light") => ColorScheme::Light,
+        _ => ColorScheme::NoPreference,
+    }
+}
+
+/// Look up `key` in `[group]` of a GLib keyfile
+fn keyfile_value<'a>(contents: &'a str, group: &str, key: &str) -> Option<&'a str> {
+    let mut in_group = false;
+    for line in contents.lines().map(str::trim) {
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            in_group = name == group;
+        } else if in_group {
+            if let Some((k, v)) = line.split_once('=') {
+                if k.trim() == key {
+                    return Some(v.trim());
+                }
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEYFILE: &str = "\
+[org/gnome/desktop/wm/preferences]
+color-scheme='prefer-dark'
+
+[org/gnome/desktop/interface]
+gtk-theme='Adwaita'
+color-scheme = 'prefer-light'
+";
+
+    #[test]
+    fn parses_colors() {
+        assert_eq!(parse_color("#a6d9ff"), Ok(ColorU8::from_rgba(0xa6, 0xd9, 0xff, 0xff)));
+        assert_eq!(parse_color("#A6D9FF80"), Ok(ColorU8::from_rgba(0xa6, 0xd9, 0xff, 0x80)));
+        for invalid in ["a6d9ff", "#a6d9f", "#a6d9ff8", "#a6d9fg", "", "#", "#ééé"] {
+            assert!(parse_color(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn reads_keyfile_values_from_their_group() {
+        assert_eq!(keyfile_value(KEYFILE, "org/gnome/desktop/interface", "color-scheme"), Some("'prefer-light'"));
+        assert_eq!(keyfile_value(KEYFILE, "org/gnome/desktop/interface", "gtk-theme"), Some("'Adwaita'"));
+        assert_eq!(keyfile_value(KEYFILE, "org/gnome/desktop/interface", "font-name"), None);
+        assert_eq!(keyfile_value(KEYFILE, "org/gnome/desktop/a11y/interface", "color-scheme"), None);
+    }
+
+    #[test]
+    fn color_scheme_sources_in_order() {
+        let high_contrast = "[org/gnome/desktop/a11y/interface]\nhigh-contrast=true\n";
+        // The override beats the keyfile, which beats GTK_THEME
+        assert_eq!(color_scheme_from(Some("dark"), Some(KEYFILE), Some("Adwaita:light")), ColorScheme::Dark);
+        assert_eq!(color_scheme_from(Some("2"), None, None), ColorScheme::Light);
+        assert_eq!(color_scheme_from(None, Some(KEYFILE), Some("Adwaita:dark")), ColorScheme::Light);
+        assert_eq!(color_scheme_from(None, Some(high_contrast), Some("Adwaita:dark")), ColorScheme::HighContrast);
+        // A keyfile without the setting leaves it to GTK_THEME
+        assert_eq!(color_scheme_from(None, Some("[org/gnome/desktop/interface]\n"), Some("Adwaita:dark")), ColorScheme::Dark);
+        assert_eq!(color_scheme_from(None, None, Some("Adwaita")), ColorScheme::NoPreference);
+    }
+
+    #[test]
+    fn user_theme_applies_colors_over_its_base() {
+        let table: toml::Table = "base = \"light\"\naccent = \"#ff0000\"\n".parse().unwrap();
+        let theme = Theme::from_table("mine", &table).unwrap();
+        assert_eq!(theme.name, "mine");
+        assert_eq!(theme.accent, ColorU8::from_rgba(0xff, 0, 0, 0xff));
+        assert_eq!(theme.background, Theme::light().background);
+
+        let error = |toml: &str| Theme::from_table("mine", &toml.parse().unwrap()).err().unwrap();
+        assert_eq!(error("base = \"sepia\""), "base: unknown built-in theme \"sepia\"");
+        assert_eq!(error("glow = \"#ff0000\""), "glow: unknown color name");
+        assert!(error("accent = \"red\"").starts_with("accent: expected a color"));
+    }
+}
@@ -1,30 +1,221 @@
-use crate::compose::{ComposeEntry, ComposeIndex};
-use cosmic_text::{Attrs, Buffer, FontSystem, Hinting, Metrics, Shaping, SwashCache};
+use crate::charinfo::{display_text, is_combining, is_emoji, CharInfo, Representation, SYMBOL_CATEGORIES};
+use crate::compose::{ComposeEntry, ComposeIndex, COMBINING_MARKS};
+use crate::diff::{route_text, ChangeKind, KeymapDiff};
+use crate::drill::{Drill, Feedback};
+use crate::history::History;
+use crate::keyboard::{Decoded, KeyOutput};
+use crate::rank;
+use crate::theme::Theme;
+use crate::xcompose::{self, RuleProposal};
+use cosmic_text::{Attrs, Buffer, Family, FontSystem, Hinting, Metrics, Shaping, SwashCache};
 use smithay_client_toolkit::{
     reexports::client::protocol::{wl_shm, wl_surface::WlSurface},
     shm::{slot::SlotPool, Shm},
 };
+use std::collections::HashMap;
 use std::sync::Arc;
-use tiny_skia::{Color, Pixmap};
+use tiny_skia::{Color, ColorU8, Pixmap};
 use xkbcommon::xkb;
 
-fn bg_color() -> Color { Color::from_rgba(0.11, 0.11, 0.13, 1.0).unwrap() }
-fn text_primary() -> Color { Color::from_rgba(1.0, 1.0, 1.0, 1.0).unwrap() }
-fn text_secondary() -> Color { Color::from_rgba(0.75, 0.75, 0.78, 1.0).unwrap() }
-fn text_tertiary() -> Color { Color::from_rgba(0.5, 0.5, 0.55, 1.0).unwrap() }
-fn accent_color() -> Color { Color::from_rgba(0.65, 0.85, 1.0, 1.0).unwrap() }  // Bright target key color
-
-// Keycap styling colors
-fn keycap_bg() -> tiny_skia::ColorU8 { tiny_skia::ColorU8::from_rgba(48, 48, 54, 255) }
-fn keycap_border() -> tiny_skia::ColorU8 { tiny_skia::ColorU8::from_rgba(70, 70, 78, 255) }
-fn modifier_keycap_bg() -> tiny_skia::ColorU8 { tiny_skia::ColorU8::from_rgba(34, 34, 40, 255) }
-fn modifier_text() -> Color { Color::from_rgba(0.6, 0.6, 0.65, 1.0).unwrap() }  // Dimmer modifier text
-fn connector_color() -> Color { Color::from_rgba(0.65, 0.65, 0.7, 1.0).unwrap() }  // Brighter + and → symbols
-fn divider_color() -> tiny_skia::ColorU8 { tiny_skia::ColorU8::from_rgba(50, 50, 55, 255) }
-fn hover_highlight() -> tiny_skia::ColorU8 { tiny_skia::ColorU8::from_rgba(35, 35, 40, 255) }
+/// Appearance settings for the renderer
+#[derive(Clone)]
+pub struct Appearance {
+    pub theme: Theme,
+    // Size of the large character glyph in each row
+    pub char_font_size: f32,
+    // Size of keycap labels; modifier keycaps use 3/4 of it
+    pub key_font_size: f32,
+    // Font families for the interface and for the large result glyphs;
+    // None lets cosmic-text pick the default sans-serif font
+    pub ui_font: Option<String>,
+    pub char_font: Option<String>,
+    // Color font for emoji; None picks an installed one with "Emoji" in its name
+    pub emoji_font: Option<String>,
+}
+
+impl Default for Appearance {
+    fn default() -> Self {
+        Appearance {
+            theme: Theme::dark(),
+            char_font_size: 28.0,
+            key_font_size: 16.0,
+            ui_font: None,
+            char_font: None,
+            emoji_font: None,
+        }
+    }
+}
 
 // Layout constants
 const LEFT_MARGIN: f32 = 24.0;
+const RESULTS_TOP: f32 = 68.0;
+const ROW_HEIGHT: f32 = 34.0;
+const MAX_RESULTS: usize = 10;
+const BOTTOM_PADDING: f32 = 16.0;
+const SYMBOL_FONT_SIZE: f32 = 14.0;
+// Gap between a keycap and the following "+" or "→" symbol
+const COLUMN_GAP: f32 = 6.0;
+const KEYCAP_HEIGHT: f32 = 24.0;
+
+// Detail panel below the results: a fixed number of lines so the window
+// doesn't change height while hovering
+const DETAIL_LINES: usize = 7;
+const DETAIL_LINE_HEIGHT: f32 = 18.0;
+const DETAIL_TOP_GAP: f32 = 14.0;
+const DETAIL_LABEL_WIDTH: f32 = 64.0;
+const DETAIL_FONT_SIZE: f32 = 12.0;
+
+// Room for the ★ marker at the end of pinned rows
+const FAVORITE_MARKER_WIDTH: f32 = 20.0;
+
+// Practice screen
+const DRILL_TARGET_SIZE: f32 = 56.0;
+const DRILL_HEIGHT: u32 = 300;
+// Characters to practice when there is no filter or history to start from
+const DRILL_DEFAULT_CARDS: usize = 20;
+
+// Modifiers used in key sequences, in the order they are written
+const ROW_MODIFIERS: [&str; 2] = ["AltGr", "Shift"];
+
+// Key decoder screen: rows of keycaps for the main key block, each as
+// wide as its label
+const DECODER_GRID_TOP: f32 = 112.0;
+const DECODER_GAP: f32 = 6.0;
+const DECODER_CELL: f32 = KEYCAP_HEIGHT + DECODER_GAP;
+// Row stagger of a standard keyboard, in cells
+const DECODER_ROW_OFFSETS: [f32; 4] = [0.0, 0.5, 0.75, 0.25];
+
+// Compose rule preview screen
+const RULE_PREVIEW_HEIGHT: u32 = 200;
+
+// Keymap comparison screen
+const DIFF_ROWS_TOP: f32 = 72.0;
+const DIFF_ROW_HEIGHT: f32 = 24.0;
+const DIFF_VISIBLE_ROWS: usize = 14;
+const DIFF_MARKER_WIDTH: f32 = 18.0;
+const DIFF_CHAR_WIDTH: f32 = 36.0;
+const DIFF_FONT_SIZE: f32 = 12.0;
+
+// Typing this starts a search by character name instead of base letter
+const NAME_SEARCH_PREFIX: char = '/';
+
+// Shaped text buffers are cached by (text, physical font size bits, font family)
+type TextKey = (String, u32, Option<String>);
+const TEXT_CACHE_LIMIT: usize = 512;
+
+/// Which font can render a result character
+#[derive(Clone)]
+enum GlyphCoverage {
+    /// The glyph font, or cosmic-text's fallback fonts, cover it
+    Default,
+    /// Only found by scanning the font database; draw with this family
+    Family(String),
+    /// No installed font has a glyph for it
+    Missing,
+}
+
+// Window size limits; the empty-input screen uses the default height so the
+// hints stay at the bottom
+pub const MIN_WIDTH: u32 = 280;
+const MIN_HEIGHT: u32 = 160;
+pub const DEFAULT_HEIGHT: u32 = 420;
+
+/// Position and size in logical pixels
+#[derive(Clone, Copy)]
+struct Rect {
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+}
+
+/// How a result row is highlighted
+#[derive(Clone, Copy, Default)]
+struct RowState {
+    // Just copied
+    copied: bool,
+    // Under the pointer or selected with the keyboard
+    hovered: bool,
+    // Typed with the modifiers being held; its keycaps are lit
+    lit: bool,
+}
+
+/// Column positions for result rows, relative to the left margin.
+/// Derived from the widest keycaps in the current results so long
+/// modifier names like "AltGr-Shift" don't overflow into the next column.
+struct RowLayout {
+    col_modifier: f32,
+    col_plus: f32,
+    col_key1: f32,
+    col_arrow: f32,
+    col_key2: f32,
+    col_more: f32,
+    width: f32,
+}
+
+/// "+2" badge for a row whose character has other routes
+fn more_label(entry: &ComposeEntry) -> String {
+    format!("+{}", entry.alternatives.len())
+}
+
+/// Code point label like "U+1F600" for characters without a font
+fn codepoint_label(text: &str) -> String {
+    text.chars()
+        .map(|c| format!("U+{:04X}", c as u32))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Split a key sequence into (modifier, first key, second key)
+/// Direct: "AltGr-w" → (Some("AltGr"), "w", None)
+/// Dead key: "AltGr-`  e" → (Some("AltGr"), "`", Some("e")) (double space separates steps)
+fn split_key_sequence(sequence: &str) -> (Option<&str>, &str, Option<&str>) {
+    let (first_part, second_part) = match sequence.find("  ") {
+        Some(space_pos) => (&sequence[..space_pos], Some(sequence[space_pos..].trim())),
+        None => (sequence, None),
+    };
+
+    // Skip the last character so that a "-" key itself (e.g. "AltGr--") stays intact
+    let last_char = first_part.char_indices().last().map_or(0, |(i, _)| i);
+    match first_part[..last_char].rfind('-') {
+        Some(dash_pos) => (Some(&first_part[..dash_pos]), &first_part[dash_pos + 1..], second_part),
+        None => (None, first_part, second_part),
+    }
+}
+
+/// Steps after the first one of a key sequence: "e" or "-  Shift-."
+fn later_steps(steps: &str) -> impl Iterator<Item = &str> {
+    steps.split("  ").filter(|step| !step.is_empty())
+}
+
+/// "12/15 correct · 80% · avg 2.1s"
+fn drill_stats(drill: &Drill) -> String {
+    if drill.attempts == 0 {
+        return "No attempts yet".to_string();
+    }
+    let accuracy = drill.correct * 100 / drill.attempts;
+    let mut stats = format!("{}/{} correct · {}%", drill.correct, drill.attempts, accuracy);
+    if let Some(average) = drill.average_time() {
+        stats.push_str(&format!(" · avg {:.1}s", average.as_secs_f32()));
+    }
+    stats
+}
+
+fn decoder_footer() -> String {
+    "Hold modifiers or press keys · ESC to stop".to_string()
+}
+
+fn rule_preview_footer() -> String {
+    "Return to add it · ESC to cancel".to_string()
+}
+
+fn diff_footer() -> String {
+    "↑↓ to scroll · ESC to close".to_string()
+}
+
+fn drill_footer() -> String {
+    "Type it with your keyboard · ESC to stop".to_string()
+}
 
 /// A clickable region with its character
 struct ClickRegion {
@@ -35,16 +226,26 @@ struct ClickRegion {
 
 pub struct CharRefUI {
     surface: WlSurface,
+    // Logical (surface) size; all layout is done in these units
     width: u32,
     height: u32,
+    // Output scale factor; the pixmap is width*scale × height*scale physical pixels
+    scale: f32,
     pixmap: Pixmap,
     pool: SlotPool,
 
     font_system: FontSystem,
     swash_cache: SwashCache,
+    text_cache: HashMap<TextKey, Buffer>,
+    glyph_coverage: HashMap<String, GlyphCoverage>,
+    // Font family for emoji, if a color emoji font is installed
+    emoji_family: Option<String>,
 
+    appearance: Appearance,
     input_text: String,
     compose_index: Arc<ComposeIndex>,
+    // Favorites and recent copies, listed when the filter is empty
+    history: History,
 
     // Track clickable regions for the current render
     click_regions: Vec<ClickRegion>,
@@ -52,6 +253,39 @@ pub struct CharRefUI {
     copied_row: Option<usize>,
     // Track which row is being hovered
     hovered_row: Option<usize>,
+    // Row selected with the keyboard
+    selected_row: Option<usize>,
+    // Keys for copying other representations, shown in the detail panel
+    detail_hint: String,
+    // Key for adding a Compose rule, shown for characters without a route
+    rule_hint: String,
+    // Compose rule waiting for confirmation, replaces the search screen
+    rule_proposal: Option<RuleProposal>,
+    // Layouts and options that can type a character without a route; None
+    // while the search runs
+    suggestions: HashMap<String, Option<Vec<String>>>,
+    // Changes with the index; a search started before a layout switch is
+    // for the old layout
+    suggestions_generation: u64,
+    // One-off message in the header, until the next key press
+    notice: Option<String>,
+    // Preferred languages for ranking variants
+    languages: Vec<String>,
+    // Results for (input, generation); the generation changes with the
+    // index, the history and the languages, which results also depend on
+    results_cache: Option<(String, u64, Vec<ComposeEntry>)>,
+    results_generation: u64,
+    // Practice mode, replaces the search screen while active
+    drill: Option<Drill>,
+    // Live key decoder, also replaces the search screen
+    decoder: Option<Decoded>,
+    // Keymap comparison, replaces the search screen for `kbdviz diff`
+    diff: Option<KeymapDiff>,
+    // First row shown, and the widths of the before and after columns
+    diff_scroll: usize,
+    diff_columns: (f32, f32),
+    // Shift/AltGr currently held; rows typed with them are lit, others dimmed
+    held_modifiers: Vec<&'static str>,
 }
 
 impl CharRefUI {
@@ -61,6 +295,8 @@ impl CharRefUI {
         height: u32,
         shm: &Shm,
         compose_index: Arc<ComposeIndex>,
+        appearance: Appearance,
+        history: History,
     ) -> Self {
         let pixmap = Pixmap::new(width, height).unwrap();
         let pool = SlotPool::new((width * height * 4) as usize, shm)
@@ -69,45 +305,641 @@ impl CharRefUI {
         let font_system = FontSystem::new();
         let swash_cache = SwashCache::new();
 
+        // Warn early about configured fonts that aren't installed
+        for family in [&appearance.ui_font, &appearance.char_font, &appearance.emoji_font].into_iter().flatten() {
+            let installed = font_system.db().faces().any(|face| {
+                face.families.iter().any(|(name, _)| name.eq_ignore_ascii_case(family))
+            });
+            if !installed {
+                eprintln!("Font family '{}' is not installed, using fallback fonts", family);
+            }
+        }
+
+        let emoji_family = appearance.emoji_font.clone().or_else(|| find_emoji_family(&font_system));
+
         Self {
             surface: surface.clone(),
             width,
             height,
+            scale: 1.0,
             pixmap,
             pool,
             font_system,
             swash_cache,
+            text_cache: HashMap::new(),
+            glyph_coverage: HashMap::new(),
+            emoji_family,
+            appearance,
             input_text: String::new(),
             compose_index,
+            history,
             click_regions: Vec::new(),
             copied_row: None,
             hovered_row: None,
+            selected_row: None,
+            detail_hint: String::new(),
+            rule_hint: String::new(),
+            rule_proposal: None,
+            suggestions: HashMap::new(),
+            suggestions_generation: 0,
+            notice: None,
+            languages: Vec::new(),
+            results_cache: None,
+            results_generation: 0,
+            drill: None,
+            decoder: None,
+            diff: None,
+            diff_scroll: 0,
+            diff_columns: (0.0, 0.0),
+            held_modifiers: Vec::new(),
+        }
+    }
+
+    /// Update the held modifiers, returns true if the rows need a redraw
+    pub fn set_held_modifiers(&mut self, modifiers: Vec<&'static str>) -> bool {
+        // Only the modifiers that appear in key sequences matter
+        let relevant: Vec<&'static str> = modifiers
+            .into_iter()
+            .filter(|m| ROW_MODIFIERS.contains(m))
+            .collect();
+        if relevant == self.held_modifiers {
+            return false;
+        }
+        self.held_modifiers = relevant;
+        true
+    }
+
+    /// Whether a row is typed with exactly the held modifiers; None when
+    /// nothing is held
+    fn matches_held_modifiers(&self, entry: &ComposeEntry) -> Option<bool> {
+        if self.held_modifiers.is_empty() {
+            return None;
+        }
+        let (modifier, _, _) = split_key_sequence(&entry.key_sequence);
+        let row: Vec<&str> = modifier.map(|m| m.split('-').collect()).unwrap_or_default();
+        Some(row.len() == self.held_modifiers.len() && self.held_modifiers.iter().all(|m| row.contains(m)))
+    }
+
+    /// Set the hint line at the bottom of the detail panel
+    pub fn set_detail_hint(&mut self, hint: String) {
+        self.detail_hint = hint;
+    }
+
+    /// Set the hint shown in the details of characters without a route
+    pub fn set_rule_hint(&mut self, hint: String) {
+        self.rule_hint = hint;
+    }
+
+    /// Show a one-off message in the header until the next key press
+    pub fn set_notice(&mut self, notice: String) {
+        self.notice = Some(notice);
+    }
+
+    /// Set the languages whose accented letters rank first
+    pub fn set_languages(&mut self, languages: Vec<String>) {
+        self.languages = languages;
+        self.results_generation += 1;
+    }
+
+    /// Set the output scale factor (integer buffer scale or fractional scale)
+    /// and reallocate the pixmap at the matching physical size
+    pub fn set_scale(&mut self, scale: f64) {
+        self.scale = scale as f32;
+        // Cached buffers were shaped at the old physical font size
+        self.text_cache.clear();
+        let (w, h) = self.physical_size();
+        self.pixmap = Pixmap::new(w, h).unwrap();
+    }
+
+    /// Size of the rendered buffer in physical pixels
+    pub fn physical_size(&self) -> (u32, u32) {
+        (
+            (self.width as f32 * self.scale).round() as u32,
+            (self.height as f32 * self.scale).round() as u32,
+        )
+    }
+
+    /// Resize to a new logical size (from the compositor's configure event)
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width == self.width && height == self.height {
+            return;
+        }
+        self.width = width;
+        self.height = height;
+        let (w, h) = self.physical_size();
+        self.pixmap = Pixmap::new(w, h).unwrap();
+        if let Err(e) = self.pool.resize((w * h * 4) as usize) {
+            eprintln!("Failed to resize slot pool: {}", e);
+        }
+    }
+
+    /// Logical size that fits the current content
+    pub fn preferred_size(&mut self) -> (u32, u32) {
+        if let Some(rows) = self.decoder.as_ref().map(|decoded| decoded.rows.clone()) {
+            let rows_width = rows
+                .iter()
+                .zip(DECODER_ROW_OFFSETS)
+                .map(|(row, offset)| {
+                    let keys: f32 = row.iter().map(|(output, is_dead)| self.keycap_width(output, *is_dead) + DECODER_GAP).sum();
+                    offset * DECODER_CELL + keys
+                })
+                .fold(0.0, f32::max);
+            let height = DECODER_GRID_TOP + rows.len() as f32 * DECODER_CELL + 56.0;
+            let width = (LEFT_MARGIN * 2.0 + rows_width).max(LEFT_MARGIN * 2.0 + self.measure_text(&decoder_footer(), 12.0));
+            return ((width.ceil() as u32).max(MIN_WIDTH), height.ceil() as u32);
+        }
+
+        if let Some(diff) = &self.diff {
+            let (before, after) = self.diff_columns;
+            let header = format!("{} → {}", diff.from, diff.to);
+            let summary = diff.summary();
+            let rows_width = DIFF_MARKER_WIDTH + DIFF_CHAR_WIDTH + before + COLUMN_GAP * 2.0 + after;
+            let width = LEFT_MARGIN * 2.0
+                + rows_width
+                    .max(self.measure_text(&header, 14.0))
+                    .max(self.measure_text(&summary, 12.0))
+                    .max(self.measure_text(&diff_footer(), 12.0));
+            let height = DIFF_ROWS_TOP + DIFF_VISIBLE_ROWS as f32 * DIFF_ROW_HEIGHT + 56.0;
+            return ((width.ceil() as u32).max(MIN_WIDTH), height.ceil() as u32);
+        }
+
+        if let Some(proposal) = &self.rule_proposal {
+            let line = proposal.line.clone();
+            let width = LEFT_MARGIN * 2.0 + self.measure_text(&line, 13.0).max(self.measure_text(&rule_preview_footer(), 12.0));
+            return ((width.ceil() as u32).max(MIN_WIDTH), RULE_PREVIEW_HEIGHT);
+        }
+
+        if let Some(drill) = &self.drill {
+            let stats = drill_stats(drill);
+            let width = LEFT_MARGIN * 2.0 + self.measure_text(&stats, 12.0).max(self.measure_text(&drill_footer(), 12.0));
+            return ((width.ceil() as u32).max(MIN_WIDTH), DRILL_HEIGHT);
+        }
+
+        let results = self.current_results();
+        // The hints screen, when there is no history to list either
+        if self.input_text.is_empty() && results.is_empty() {
+            return (MIN_WIDTH, DEFAULT_HEIGHT);
+        }
+
+        let layout = self.compute_layout(&results);
+        let rows = results.len().max(1) as f32;
+
+        let mut content_width = layout.width;
+        if results.iter().any(|entry| self.history.is_favorite(&entry.character)) {
+            content_width += FAVORITE_MARKER_WIDTH;
+        }
+        let mut height = RESULTS_TOP + rows * ROW_HEIGHT + BOTTOM_PADDING;
+        if !results.is_empty() {
+            // Wide enough for the details of any row, so hovering doesn't resize
+            for entry in &results {
+                for (label, value) in self.detail_lines(entry) {
+                    let label_width = if label.is_empty() { 0.0 } else { DETAIL_LABEL_WIDTH };
+                    content_width = content_width.max(label_width + self.measure_text(&value, DETAIL_FONT_SIZE));
+                }
+            }
+            height += DETAIL_TOP_GAP + DETAIL_LINES as f32 * DETAIL_LINE_HEIGHT;
+        }
+        if results.is_empty() && self.input_text == NAME_SEARCH_PREFIX.to_string() {
+            // Room for the category list under the name search hint
+            height += ROW_HEIGHT;
+            content_width = content_width.max(self.measure_text(&SYMBOL_CATEGORIES.join(" "), 12.0));
+        }
+
+        let width = (LEFT_MARGIN * 2.0 + content_width).ceil() as u32;
+        (width.max(MIN_WIDTH), (height.ceil() as u32).max(MIN_HEIGHT))
+    }
+
+    /// Results for the input. Called many times per frame and key, so they
+    /// are kept until the input, the index or the history changes.
+    fn current_results(&mut self) -> Vec<ComposeEntry> {
+        if let Some((input, generation, results)) = &self.results_cache {
+            if *input == self.input_text && *generation == self.results_generation {
+                return results.clone();
+            }
+        }
+        let results = self.find_results();
+        self.results_cache = Some((self.input_text.clone(), self.results_generation, results.clone()));
+        results
+    }
+
+    fn find_results(&self) -> Vec<ComposeEntry> {
+        if self.input_text.is_empty() {
+            // Favorites and recently copied characters, each with its
+            // shortest route on the current layout
+            self.history
+                .characters()
+                .into_iter()
+                .filter_map(|character| self.compose_index.entry_for(character))
+                .take(MAX_RESULTS)
+                .collect()
+        } else {
+            let query = self.input_text.strip_prefix(NAME_SEARCH_PREFIX);
+            let mut results = match query {
+                Some(query) => self.compose_index.search_names(query),
+                None => self.compose_index.find_variants(&self.input_text),
+            };
+            // Nothing the layout can type: list what it can't, so a
+            // Compose rule can be added for it
+            if results.is_empty() {
+                results = match query {
+                    Some(query) => self.compose_index.find_unreachable(query, true),
+                    None => self.compose_index.find_unreachable(&self.input_text, false),
+                };
+            }
+            rank::rank(&mut results, &self.history, &self.languages);
+            results.truncate(MAX_RESULTS);
+            results
+        }
+    }
+
+    /// Compute column positions from the measured widths of all rows
+    fn compute_layout(&mut self, results: &[ComposeEntry]) -> RowLayout {
+        let mut char_width: f32 = 0.0;
+        let mut modifier_width: f32 = 0.0;
+        let mut key1_width: f32 = 0.0;
+        let mut key2_width: f32 = 0.0;
+
+        for entry in results {
+            char_width = char_width.max(self.glyph_width(&display_text(&entry.character)));
+            let (modifier, key1, key2) = split_key_sequence(&entry.key_sequence);
+            match modifier {
+                Some(modifier) => {
+                    modifier_width = modifier_width.max(self.keycap_width(modifier, true));
+                    key1_width = key1_width.max(self.keycap_width(key1, false));
+                }
+                // Characters without a route say so in the modifier column
+                None if key1.is_empty() => modifier_width = modifier_width.max(self.measure_text("no route", SYMBOL_FONT_SIZE)),
+                // Sequences without a modifier are drawn in the modifier column
+                None => modifier_width = modifier_width.max(self.keycap_width(key1, false)),
+            }
+            if let Some(steps) = key2 {
+                key2_width = key2_width.max(self.steps_width(steps));
+            }
+        }
+
+        let plus_width = self.measure_text("+", SYMBOL_FONT_SIZE) + COLUMN_GAP;
+        let arrow_width = self.measure_text("→", SYMBOL_FONT_SIZE) + COLUMN_GAP;
+
+        let col_modifier = (char_width + 12.0).max(45.0);
+        let col_plus = col_modifier + modifier_width + COLUMN_GAP;
+        let col_key1 = col_plus + plus_width;
+        let col_arrow = col_key1 + key1_width + COLUMN_GAP;
+        let col_key2 = col_arrow + arrow_width;
+        let keys_width = if key2_width > 0.0 {
+            col_key2 + key2_width
+        } else {
+            col_key1 + key1_width
+        };
+
+        // "+N" after the keys when a character has other routes
+        let col_more = keys_width + COLUMN_GAP * 2.0;
+        let more_width = results
+            .iter()
+            .filter(|entry| !entry.alternatives.is_empty())
+            .map(|entry| self.measure_text(&more_label(entry), SYMBOL_FONT_SIZE))
+            .fold(0.0, f32::max);
+        let width = if more_width > 0.0 { col_more + more_width } else { keys_width };
+
+        RowLayout { col_modifier, col_plus, col_key1, col_arrow, col_key2, col_more, width }
+    }
+
+    /// Width of the steps after the first, with arrows between them
+    fn steps_width(&mut self, steps: &str) -> f32 {
+        let arrow_width = self.measure_text("→", SYMBOL_FONT_SIZE) + COLUMN_GAP;
+        let widths: Vec<f32> = later_steps(steps).map(|step| self.keycap_width(step, false)).collect();
+        widths.iter().sum::<f32>() + widths.len().saturating_sub(1) as f32 * (arrow_width + COLUMN_GAP)
+    }
+
+    /// Draw the steps after the first, e.g. Compose then "-" then ">"
+    fn draw_steps(&mut self, steps: &str, x: f32, y: f32) {
+        let symbol_color = self.appearance.theme.connector;
+        let arrow_width = self.measure_text("→", SYMBOL_FONT_SIZE) + COLUMN_GAP;
+        let mut x = x;
+        for (index, step) in later_steps(steps).enumerate() {
+            if index > 0 {
+                self.draw_text_colored("→", x, y + 3.0, SYMBOL_FONT_SIZE, symbol_color);
+                x += arrow_width;
+            }
+            x += self.draw_keycap(step, x, y, false) + COLUMN_GAP;
+        }
+    }
+
+    pub fn is_decoding(&self) -> bool {
+        self.decoder.is_some()
+    }
+
+    /// Show (or with None, leave) the live key decoder
+    pub fn set_decoder(&mut self, decoded: Option<Decoded>) {
+        self.decoder = decoded;
+        self.click_regions.clear();
+        self.hovered_row = None;
+    }
+
+    /// Use the index of a new keymap, keeping the filter and the selected
+    /// character (if the new layout can still type it)
+    pub fn set_compose_index(&mut self, compose_index: Arc<ComposeIndex>) {
+        let selected = self
+            .selected_row
+            .and_then(|row| self.current_results().into_iter().nth(row))
+            .map(|entry| entry.character);
+        self.compose_index = compose_index;
+        self.results_generation += 1;
+        // Found for the old layout
+        self.suggestions.clear();
+        self.suggestions_generation += 1;
+        self.copied_row = None;
+        self.hovered_row = None;
+        self.click_regions.clear();
+        self.selected_row = selected.and_then(|character| {
+            self.current_results().iter().position(|entry| entry.character == character)
+        });
+    }
+
+    /// Whether the input is a name search ("/arrow") rather than a letter
+    pub fn is_searching_names(&self) -> bool {
+        self.input_text.starts_with(NAME_SEARCH_PREFIX)
+    }
+
+    pub fn is_showing_diff(&self) -> bool {
+        self.diff.is_some()
+    }
+
+    /// Show a keymap comparison instead of the search screen
+    pub fn set_diff(&mut self, diff: Option<KeymapDiff>) {
+        self.diff_columns = (0.0, 0.0);
+        if let Some(diff) = &diff {
+            // Measure once; the columns stay put while scrolling
+            let mut columns = (self.measure_text(&diff.from, DIFF_FONT_SIZE), self.measure_text(&diff.to, DIFF_FONT_SIZE));
+            for change in &diff.changes {
+                columns.0 = columns.0.max(self.measure_text(&route_text(&change.before), DIFF_FONT_SIZE));
+                columns.1 = columns.1.max(self.measure_text(&route_text(&change.after), DIFF_FONT_SIZE));
+            }
+            self.diff_columns = columns;
         }
+        self.diff = diff;
+        self.diff_scroll = 0;
+        self.click_regions.clear();
+        self.hovered_row = None;
+    }
+
+    pub fn is_drilling(&self) -> bool {
+        self.drill.is_some()
+    }
+
+    /// Start practicing the characters on screen, or the most common ones
+    /// if the screen is empty. Returns false if there is nothing to practice.
+    pub fn start_drill(&mut self) -> bool {
+        let mut entries = self.current_results();
+        if entries.is_empty() {
+            entries = self.compose_index.all_entries();
+            rank::rank(&mut entries, &self.history, &self.languages);
+            entries.truncate(DRILL_DEFAULT_CARDS);
+        }
+        self.drill = Drill::new(entries);
+        self.click_regions.clear();
+        self.hovered_row = None;
+        self.drill.is_some()
+    }
+
+    pub fn stop_drill(&mut self) {
+        if let Some(drill) = self.drill.take() {
+            eprintln!("Practice: {}", drill_stats(&drill));
+        }
+    }
+
+    /// Feed a decoded key press to the drill, returns true if it needs a redraw
+    pub fn drill_key(&mut self, output: KeyOutput) -> bool {
+        self.drill.as_mut().is_some_and(|drill| drill.handle_key(output))
     }
 
     /// Set the filter character (for --char option)
     pub fn set_filter(&mut self, c: char) {
         self.input_text.clear();
         self.input_text.push(c);
+        self.selected_row = None;
     }
 
-    pub fn handle_key_press(&mut self, _raw_code: u32, keysym: xkb::Keysym) {
-        // Clear copied indicator on any key press
+    /// Remove the last character of a name search, or clear the filter
+    pub fn erase(&mut self) {
+        if self.input_text.starts_with(NAME_SEARCH_PREFIX) && self.input_text.chars().count() > 1 {
+            self.input_text.pop();
+            self.copied_row = None;
+            self.selected_row = None;
+        } else {
+            self.clear();
+        }
+    }
+
+    /// Clear the filter
+    pub fn clear(&mut self) {
+        self.input_text.clear();
         self.copied_row = None;
+        self.selected_row = None;
+    }
+
+    pub fn is_proposing_rule(&self) -> bool {
+        self.rule_proposal.is_some()
+    }
+
+    /// Propose a Compose rule for the current row, if it has no route
+    pub fn propose_compose_rule(&mut self) -> Result<(), String> {
+        let entry = self
+            .current_row()
+            .and_then(|row| self.current_results().into_iter().nth(row))
+            .ok_or("Select a character first")?;
+        // Check the keys too, so a symbol on an AltGr level never gets a
+        // redundant rule
+        let route = self.compose_index.entry_for(&entry.character).map_or(entry.key_sequence, |known| known.key_sequence);
+        if !route.is_empty() {
+            return Err(format!("'{}' can already be typed as {}", entry.character, route.replace("  ", " ")));
+        }
+        self.rule_proposal = Some(xcompose::propose(&entry.character)?);
+        self.click_regions.clear();
+        self.hovered_row = None;
+        Ok(())
+    }
+
+    /// Start looking for layouts and options that type the current row's
+    /// character; returns the character to search for and the generation
+    /// to hand back with the results through set_suggestions.
+    pub fn start_suggestions(&mut self) -> Result<(String, u64), String> {
+        let entry = self
+            .current_row()
+            .and_then(|row| self.current_results().into_iter().nth(row))
+            .ok_or("Select a character first")?;
+        if !entry.key_sequence.is_empty() {
+            return Err(format!("'{}' can already be typed", entry.character));
+        }
+        if self.suggestions.get(&entry.character).is_some_and(Option::is_none) {
+            return Err(format!("Still searching for '{}'", entry.character));
+        }
+        self.suggestions.insert(entry.character.clone(), None);
+        Ok((entry.character, self.suggestions_generation))
+    }
+
+    /// Show suggestions for a character, unless the layout changed since
+    /// the search started
+    pub fn set_suggestions(&mut self, character: String, generation: u64, suggestions: Vec<String>) {
+        if generation != self.suggestions_generation {
+            return;
+        }
+        if let Some(pending @ None) = self.suggestions.get_mut(&character) {
+            *pending = Some(suggestions);
+        }
+    }
+
+    /// Write (or with false, drop) the proposed Compose rule
+    pub fn finish_compose_rule(&mut self, confirmed: bool) {
+        let Some(proposal) = self.rule_proposal.take() else {
+            return;
+        };
+        if !confirmed {
+            return;
+        }
+        let notice = match xcompose::write(&proposal) {
+            Ok(summary) => {
+                eprintln!("{}", summary);
+                format!("Added a Compose rule for {}; restart applications to use it", proposal.character)
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                e
+            }
+        };
+        self.set_notice(notice);
+    }
 
-        // Handle backspace
-        if keysym == xkb::Keysym::BackSpace {
-            self.input_text.clear();
+    /// Move the keyboard selection by `delta` rows, wrapping around
+    pub fn move_selection(&mut self, delta: isize) {
+        // The comparison scrolls instead, without wrapping
+        if let Some(diff) = &self.diff {
+            let last = diff.changes.len().saturating_sub(DIFF_VISIBLE_ROWS);
+            self.diff_scroll = (self.diff_scroll as isize + delta).clamp(0, last as isize) as usize;
+            return;
+        }
+        let count = self.current_results().len();
+        if count == 0 {
+            self.selected_row = None;
             return;
         }
+        self.selected_row = Some(match self.selected_row {
+            Some(row) => (row as isize + delta).rem_euclid(count as isize) as usize,
+            None if delta < 0 => count - 1,
+            None => 0,
+        });
+    }
+
+    /// The row shown in the detail panel: keyboard selection, else hover
+    fn current_row(&self) -> Option<usize> {
+        self.selected_row.or(self.hovered_row)
+    }
+
+    /// Mark the current row as copied and return its character in the
+    /// requested representation
+    pub fn copy_selected(&mut self, representation: Representation) -> Option<String> {
+        let row = self.current_row()?;
+        let entry = self.current_results().into_iter().nth(row)?;
+        self.mark_copied(&entry.character);
+        Some(representation.format(&entry.character))
+    }
+
+    /// Record a copy and highlight its row. On the empty screen the row
+    /// moves to the top of the recent list, so the selection follows it.
+    fn mark_copied(&mut self, character: &str) {
+        self.history.record_copy(character);
+        self.results_generation += 1;
+        let row = self.current_results().iter().position(|entry| entry.character == character);
+        self.copied_row = row;
+        if self.selected_row.is_some() {
+            self.selected_row = row;
+        }
+    }
+
+    /// Pin or unpin the current row
+    pub fn toggle_favorite(&mut self) {
+        let Some(entry) = self.current_row().and_then(|row| self.current_results().into_iter().nth(row)) else {
+            return;
+        };
+        let pinned = self.history.toggle_favorite(&entry.character);
+        self.results_generation += 1;
+        eprintln!("{} '{}'", if pinned { "Pinned" } else { "Unpinned" }, entry.character);
+        self.clamp_selection();
+    }
+
+    /// Remove the current row from favorites and history
+    pub fn forget_selected(&mut self) {
+        let Some(entry) = self.current_row().and_then(|row| self.current_results().into_iter().nth(row)) else {
+            return;
+        };
+        self.history.forget(&entry.character);
+        self.results_generation += 1;
+        self.copied_row = None;
+        self.clamp_selection();
+    }
+
+    /// Keep the selection on a valid row after the empty-screen list changed
+    fn clamp_selection(&mut self) {
+        let count = self.current_results().len();
+        self.selected_row = self.selected_row.filter(|_| count > 0).map(|row| row.min(count - 1));
+        self.hovered_row = self.hovered_row.filter(|&row| row < count);
+    }
+
+    /// Label/value lines for the detail panel. Always DETAIL_LINES long;
+    /// the first two lines (code point, name) have no label.
+    fn detail_lines(&self, entry: &ComposeEntry) -> Vec<(&'static str, String)> {
+        let info = CharInfo::new(&entry.character);
+
+        // Other ways to type the same character
+        let alternatives: Vec<String> = entry.alternatives
+            .iter()
+            .map(|route| route.replace("  ", " "))
+            .collect();
+
+        // Without a route, "Also" becomes the layouts that have one
+        let also = if entry.key_sequence.is_empty() {
+            match self.suggestions.get(&entry.character) {
+                Some(None) => ("Try", "searching layouts…".to_string()),
+                Some(Some(suggestions)) if suggestions.is_empty() => ("Try", "no layout or option found".to_string()),
+                Some(Some(suggestions)) => ("Try", suggestions.join(" · ")),
+                None => ("Try", "—".to_string()),
+            }
+        } else {
+            ("Also", if alternatives.is_empty() { "—".to_string() } else { alternatives.join(" · ") })
+        };
+
+        vec![
+            ("", info.code_point),
+            ("", info.name),
+            ("Category", info.category),
+            ("NFD", info.decomposition.unwrap_or_else(|| "—".to_string())),
+            ("HTML", info.html_entity),
+            also,
+            ("", if entry.key_sequence.is_empty() { self.rule_hint.clone() } else { self.detail_hint.clone() }),
+        ]
+    }
+
+    pub fn handle_key_press(&mut self, _raw_code: u32, keysym: xkb::Keysym) {
+        // Clear copied indicator and notices on any key press
+        self.copied_row = None;
+        self.notice = None;
 
         // Convert keysym to char
         let utf32 = xkb::keysym_to_utf32(keysym);
         if let Some(ch) = char::from_u32(utf32) {
-            if ch.is_alphabetic() {
+            if self.input_text.starts_with(NAME_SEARCH_PREFIX) {
+                // Name search collects whole words
+                if !ch.is_control() {
+                    self.input_text.push(ch);
+                    self.selected_row = None;
+                }
+            } else if ch == NAME_SEARCH_PREFIX {
+                self.set_filter(ch);
+            } else if ch.is_alphabetic() {
                 // Replace input with just this character (single-letter filter)
-                self.input_text.clear();
-                self.input_text.push(ch);
+                self.set_filter(ch);
+            } else if is_combining(ch) {
+                // A combining key lists all the combining marks
+                self.set_filter(COMBINING_MARKS);
             }
         }
     }
@@ -116,15 +948,12 @@ impl CharRefUI {
     pub fn handle_click(&mut self, _x: f64, y: f64) -> Option<String> {
         let y = y as f32;
 
-        for (index, region) in self.click_regions.iter().enumerate() {
-            if y >= region.y_start && y < region.y_end {
-                let character = region.character.clone();
-                self.copied_row = Some(index);
-                return Some(character);
-            }
-        }
-
-        None
+        let character = self.click_regions
+            .iter()
+            .find(|region| y >= region.y_start && y < region.y_end)
+            .map(|region| region.character.clone())?;
+        self.mark_copied(&character);
+        Some(character)
     }
 
     /// Handle mouse movement, returns true if hover state changed (needs re-render)
@@ -149,44 +978,248 @@ impl CharRefUI {
 
     pub fn render(&mut self) {
         // Clear background
-        self.pixmap.fill(bg_color());
+        let theme = self.appearance.theme.clone();
+        self.pixmap.fill(Color::from_rgba8(
+            theme.background.red(),
+            theme.background.green(),
+            theme.background.blue(),
+            theme.background.alpha(),
+        ));
+
+        if self.diff.is_some() {
+            self.draw_diff();
+        } else if self.decoder.is_some() {
+            self.draw_decoder();
+        } else if self.drill.is_some() {
+            self.draw_drill();
+        } else if self.rule_proposal.is_some() {
+            self.draw_rule_proposal();
+        } else {
+            self.draw_search();
+        }
+        // Notices show on every screen, top right
+        if let Some(notice) = self.notice.clone() {
+            let notice_x = self.width as f32 - LEFT_MARGIN - self.measure_text(&notice, 11.0);
+            self.draw_text_colored(&notice, notice_x.max(LEFT_MARGIN), 4.0, 11.0, theme.accent);
+        }
 
-        // Clear click regions from previous render
+        // Copy pixmap to Wayland buffer
+        // Use Xrgb8888 (no alpha channel) to prevent compositor from blending with windows behind
+        let (buf_width, buf_height) = (self.pixmap.width() as i32, self.pixmap.height() as i32);
+        let stride = buf_width * 4;
+        let (buffer, canvas) = self.pool
+            .create_buffer(
+                buf_width,
+                buf_height,
+                stride,
+                wl_shm::Format::Xrgb8888,
+            )
+            .expect("Failed to create buffer");
+
+        canvas.copy_from_slice(self.pixmap.data());
+
+        self.surface.attach(Some(buffer.wl_buffer()), 0, 0);
+        self.surface.damage_buffer(0, 0, buf_width, buf_height);
+    }
+
+    fn draw_decoder(&mut self) {
+        let theme = self.appearance.theme.clone();
         self.click_regions.clear();
+        let Some(decoded) = self.decoder.take() else {
+            return;
+        };
 
-        // Get results
-        let results = if self.input_text.is_empty() {
-            Vec::new()
+        self.draw_text_colored("Key decoder", LEFT_MARGIN, 18.0, 14.0, theme.text_tertiary);
+
+        let modifiers = if decoded.modifiers.is_empty() {
+            "none".to_string()
         } else {
-            self.compose_index.find_variants(&self.input_text)
+            decoded.modifiers.join(" + ")
+        };
+        self.draw_text_colored(&format!("Modifiers: {}", modifiers), LEFT_MARGIN, 44.0, 13.0, theme.text_primary);
+
+        let mut layout = decoded.layout.clone();
+        if let Some(level) = decoded.level {
+            layout.push_str(&format!(" · level {}", level));
+        }
+        self.draw_text_colored(&layout, LEFT_MARGIN, 64.0, 12.0, theme.text_secondary);
+
+        if !decoded.pending.is_empty() {
+            let pending = format!("Composing: {} …", decoded.pending.join(" "));
+            self.draw_text_colored(&pending, LEFT_MARGIN, 84.0, 12.0, theme.accent);
+        } else if let Some(last) = &decoded.last_output {
+            let last = format!("Last: {}  {}", last, codepoint_label(last));
+            self.draw_text_colored(&last, LEFT_MARGIN, 84.0, 12.0, theme.text_secondary);
+        }
+
+        // Dead keys use the modifier keycap style
+        let mut y = DECODER_GRID_TOP;
+        for (row, offset) in decoded.rows.iter().zip(DECODER_ROW_OFFSETS) {
+            let mut x = LEFT_MARGIN + offset * DECODER_CELL;
+            for (output, is_dead) in row {
+                x += self.draw_keycap(output, x, y, *is_dead) + DECODER_GAP;
+            }
+            y += DECODER_CELL;
+        }
+
+        self.draw_text_colored(&decoder_footer(), LEFT_MARGIN, self.height as f32 - 34.0, 12.0, theme.text_tertiary);
+        self.decoder = Some(decoded);
+    }
+
+    fn draw_diff(&mut self) {
+        let theme = self.appearance.theme.clone();
+        self.click_regions.clear();
+        let Some(diff) = self.diff.take() else {
+            return;
+        };
+
+        self.draw_text_colored(&format!("{} → {}", diff.from, diff.to), LEFT_MARGIN, 18.0, 14.0, theme.text_primary);
+        self.draw_text_colored(&diff.summary(), LEFT_MARGIN, 44.0, 12.0, theme.text_secondary);
+
+        let col_before = LEFT_MARGIN + DIFF_MARKER_WIDTH + DIFF_CHAR_WIDTH;
+        let col_after = col_before + self.diff_columns.0 + COLUMN_GAP * 2.0;
+        if diff.changes.is_empty() {
+            self.draw_text_colored("Both keymaps type the same characters the same way", LEFT_MARGIN, DIFF_ROWS_TOP, 12.0, theme.text_tertiary);
+        }
+
+        let mut y = DIFF_ROWS_TOP;
+        for change in diff.changes.iter().skip(self.diff_scroll).take(DIFF_VISIBLE_ROWS) {
+            // Lost characters stand out; changed routes are the quiet majority
+            let color = match change.kind {
+                ChangeKind::Gained => theme.accent,
+                ChangeKind::Lost => theme.text_primary,
+                ChangeKind::Changed => theme.text_secondary,
+            };
+            self.draw_text_colored(change.kind.marker(), LEFT_MARGIN, y, 14.0, color);
+            match self.glyph_family(&change.character) {
+                Some(family) => self.draw_text_with_family(&display_text(&change.character), LEFT_MARGIN + DIFF_MARKER_WIDTH, y - 2.0, 16.0, theme.text_primary, family.as_deref()),
+                None => self.draw_text_colored(&codepoint_label(&change.character), LEFT_MARGIN + DIFF_MARKER_WIDTH, y, 9.0, theme.text_tertiary),
+            }
+            self.draw_text_colored(&route_text(&change.before), col_before, y + 2.0, DIFF_FONT_SIZE, theme.text_secondary);
+            self.draw_text_colored(&route_text(&change.after), col_after, y + 2.0, DIFF_FONT_SIZE, color);
+            y += DIFF_ROW_HEIGHT;
+        }
+
+        let shown = format!(
+            "{}–{} of {} · {}",
+            (self.diff_scroll + 1).min(diff.changes.len()),
+            (self.diff_scroll + DIFF_VISIBLE_ROWS).min(diff.changes.len()),
+            diff.changes.len(),
+            diff_footer(),
+        );
+        self.draw_text_colored(&shown, LEFT_MARGIN, self.height as f32 - 34.0, 12.0, theme.text_tertiary);
+        self.diff = Some(diff);
+    }
+
+    fn draw_drill(&mut self) {
+        let theme = self.appearance.theme.clone();
+        self.click_regions.clear();
+        let Some(drill) = &self.drill else {
+            return;
         };
+        let target = drill.target().clone();
+        let show_hint = drill.show_hint;
+        let pending = drill.pending;
+        let stats = drill_stats(drill);
+        let feedback = match &drill.feedback {
+            Some(Feedback::Correct(time)) => Some((format!("✓ {:.1}s", time.as_secs_f32()), theme.accent)),
+            Some(Feedback::Wrong(typed)) => Some((format!("✗ got {} — try again", typed), theme.text_secondary)),
+            None => None,
+        };
+
+        self.draw_text_colored("Type this character:", LEFT_MARGIN, 18.0, 14.0, theme.text_tertiary);
+
+        let glyph = display_text(&target.character);
+        let family = self.glyph_family(&glyph).flatten();
+        self.draw_text_with_family(&glyph, LEFT_MARGIN, 44.0, DRILL_TARGET_SIZE, theme.text_primary, family.as_deref());
+        let name = CharInfo::new(&target.character).name;
+        self.draw_text_colored(&name, LEFT_MARGIN, 120.0, 12.0, theme.text_secondary);
+
+        if show_hint {
+            let layout = self.compute_layout(std::slice::from_ref(&target));
+            self.draw_result(&target, &layout, LEFT_MARGIN, 144.0, ROW_HEIGHT, RowState::default());
+        } else if pending {
+            self.draw_text_colored("…", LEFT_MARGIN, 150.0, SYMBOL_FONT_SIZE, theme.text_tertiary);
+        }
+
+        if let Some((text, color)) = feedback {
+            self.draw_text_colored(&text, LEFT_MARGIN, 192.0, 13.0, color);
+        }
+
+        let height = self.height as f32;
+        self.draw_horizontal_line(LEFT_MARGIN, height - 64.0, self.width as f32 - LEFT_MARGIN * 2.0);
+        self.draw_text_colored(&stats, LEFT_MARGIN, height - 54.0, 12.0, theme.text_secondary);
+        self.draw_text_colored(&drill_footer(), LEFT_MARGIN, height - 34.0, 12.0, theme.text_tertiary);
+    }
+
+    fn draw_rule_proposal(&mut self) {
+        let theme = self.appearance.theme.clone();
+        self.click_regions.clear();
+        let Some(proposal) = &self.rule_proposal else {
+            return;
+        };
+        let title = format!("Add a Compose rule for {}", display_text(&proposal.character));
+        let line = proposal.line.clone();
+        let path = format!("to {} (an existing file is backed up as .bak)", proposal.path.display());
+
+        self.draw_text_colored(&title, LEFT_MARGIN, 18.0, 14.0, theme.text_primary);
+        self.draw_text_colored(&line, LEFT_MARGIN, 56.0, 13.0, theme.accent);
+        self.draw_text_colored(&path, LEFT_MARGIN, 84.0, 12.0, theme.text_secondary);
+        self.draw_text_colored("Applications read it when they start", LEFT_MARGIN, 104.0, 12.0, theme.text_tertiary);
+        self.draw_text_colored(&rule_preview_footer(), LEFT_MARGIN, self.height as f32 - 34.0, 12.0, theme.text_tertiary);
+    }
+
+    /// The normal screen: filter, result rows and detail panel
+    fn draw_search(&mut self) {
+        let theme = self.appearance.theme.clone();
+
+        // Clear click regions from previous render
+        self.click_regions.clear();
+
+        // Get results
+        let results = self.current_results();
+        let layout = self.compute_layout(&results);
 
         // Render input at top
         let input_y = 18.0;
 
         // Draw input text or hint
         if self.input_text.is_empty() {
-            self.draw_text_colored("Type a letter...", LEFT_MARGIN, input_y, 14.0, text_tertiary());
+            self.draw_text_colored("Type a letter...", LEFT_MARGIN, input_y, 14.0, theme.text_tertiary);
         } else {
             // Just show the filter letter prominently
             let text = self.input_text.clone();
-            self.draw_text_colored(&text, LEFT_MARGIN, input_y, 26.0, accent_color());
+            self.draw_text_colored(&text, LEFT_MARGIN, input_y, 26.0, theme.accent);
 
             // Draw a subtle divider line below the header
             self.draw_horizontal_line(LEFT_MARGIN, 54.0, self.width as f32 - LEFT_MARGIN * 2.0);
         }
 
         // Render results with spacing adjusted for larger text
-        let row_height = 34.0;
-        let mut y = 68.0;  // More spacing after divider
-        if results.is_empty() && !self.input_text.is_empty() {
-            self.draw_text_colored("No special characters found", LEFT_MARGIN, y, 13.0, text_tertiary());
+        let row_height = ROW_HEIGHT;
+        let mut y = RESULTS_TOP;  // More spacing after divider
+        if results.is_empty() && self.input_text == NAME_SEARCH_PREFIX.to_string() {
+            self.draw_text_colored("Type a name, or a category:", LEFT_MARGIN, y, 13.0, theme.text_tertiary);
+            self.draw_text_colored(&SYMBOL_CATEGORIES.join(" "), LEFT_MARGIN, y + 20.0, 12.0, theme.text_tertiary);
+        } else if results.is_empty() && !self.input_text.is_empty() {
+            self.draw_text_colored("No special characters found", LEFT_MARGIN, y, 13.0, theme.text_tertiary);
         } else if !results.is_empty() {
-            for (index, entry) in results.iter().take(10).enumerate() {
+            for (index, entry) in results.iter().enumerate() {
                 // Check if this row is copied or hovered
-                let is_copied = self.copied_row == Some(index);
-                let is_hovered = self.hovered_row == Some(index);
-                self.draw_result(&entry, LEFT_MARGIN, y, row_height, is_copied, is_hovered);
+                let matches = self.matches_held_modifiers(entry);
+                let state = RowState {
+                    copied: self.copied_row == Some(index),
+                    hovered: self.hovered_row == Some(index) || self.selected_row == Some(index),
+                    lit: matches == Some(true),
+                };
+                self.draw_result(entry, &layout, LEFT_MARGIN, y, row_height, state);
+                if matches == Some(false) {
+                    self.dim_region(0.0, y + 3.0, self.width as f32, row_height);
+                }
+                if self.history.is_favorite(&entry.character) {
+                    let marker_x = self.width as f32 - LEFT_MARGIN - self.measure_text("★", SYMBOL_FONT_SIZE);
+                    self.draw_text_colored("★", marker_x, y + 8.0, SYMBOL_FONT_SIZE, theme.accent);
+                }
 
                 // Track clickable region
                 self.click_regions.push(ClickRegion {
@@ -197,74 +1230,130 @@ impl CharRefUI {
 
                 y += row_height;
             }
+
+            self.draw_detail_panel(&results, y);
         }
 
-        // Show hints when empty
-        if self.input_text.is_empty() {
+        // Show hints when there's no history to show instead
+        if self.input_text.is_empty() && results.is_empty() {
             let hints_y = (self.height as f32) - 80.0;
-            self.draw_text_colored("Find special characters:", LEFT_MARGIN, hints_y, 13.0, text_secondary());
-            self.draw_text_colored("Try: a e i o u c n s z l y", LEFT_MARGIN, hints_y + 20.0, 12.0, text_tertiary());
-            self.draw_text_colored("ESC to close · click to copy", LEFT_MARGIN, hints_y + 45.0, 12.0, text_tertiary());
+            self.draw_text_colored("Find special characters:", LEFT_MARGIN, hints_y, 13.0, theme.text_secondary);
+            self.draw_text_colored("Try: a e i o u c n s z l y, or / to search by name", LEFT_MARGIN, hints_y + 20.0, 12.0, theme.text_tertiary);
+            self.draw_text_colored("ESC to close · click to copy", LEFT_MARGIN, hints_y + 45.0, 12.0, theme.text_tertiary);
         }
+    }
 
-        // Copy pixmap to Wayland buffer
-        // Use Xrgb8888 (no alpha channel) to prevent compositor from blending with windows behind
-        let stride = self.width as i32 * 4;
-        let (buffer, canvas) = self.pool
-            .create_buffer(
-                self.width as i32,
-                self.height as i32,
-                stride,
-                wl_shm::Format::Xrgb8888,
-            )
-            .expect("Failed to create buffer");
+    fn draw_detail_panel(&mut self, results: &[ComposeEntry], y: f32) {
+        let theme = self.appearance.theme.clone();
+        self.draw_horizontal_line(LEFT_MARGIN, y + DETAIL_TOP_GAP / 2.0, self.width as f32 - LEFT_MARGIN * 2.0);
+        let mut line_y = y + DETAIL_TOP_GAP;
 
-        canvas.copy_from_slice(self.pixmap.data());
+        let Some(entry) = self.current_row().and_then(|row| results.get(row)) else {
+            self.draw_text_colored("Hover or use ↑ ↓ to see details", LEFT_MARGIN, line_y, DETAIL_FONT_SIZE, theme.text_tertiary);
+            return;
+        };
 
-        self.surface.attach(Some(buffer.wl_buffer()), 0, 0);
-        self.surface.damage_buffer(0, 0, self.width as i32, self.height as i32);
+        let lines = self.detail_lines(entry);
+        let last = lines.len() - 1;
+        for (index, (label, value)) in lines.into_iter().enumerate() {
+            let color = match index {
+                0 => theme.accent,
+                1 => theme.text_primary,
+                _ if index == last => theme.text_tertiary,
+                _ => theme.text_secondary,
+            };
+            if label.is_empty() {
+                self.draw_text_colored(&value, LEFT_MARGIN, line_y, DETAIL_FONT_SIZE, color);
+            } else {
+                self.draw_text_colored(label, LEFT_MARGIN, line_y, DETAIL_FONT_SIZE, theme.text_tertiary);
+                self.draw_text_colored(&value, LEFT_MARGIN + DETAIL_LABEL_WIDTH, line_y, DETAIL_FONT_SIZE, color);
+            }
+            line_y += DETAIL_LINE_HEIGHT;
+        }
     }
 
-    fn draw_row_highlight(&mut self, x: f32, y: f32, w: f32, h: f32, color: tiny_skia::ColorU8) {
+    // The draw_* primitives take logical coordinates and convert them to
+    // physical pixels using the current scale factor.
+
+    fn draw_row_highlight(&mut self, x: f32, y: f32, w: f32, h: f32, color: ColorU8) {
         let highlight = color.premultiply();
+        let s = self.scale;
+        let (pw, ph) = (self.pixmap.width(), self.pixmap.height());
 
-        let x_start = x.max(0.0) as usize;
-        let x_end = (x + w).min(self.width as f32) as usize;
-        let y_start = y.max(0.0) as usize;
-        let y_end = (y + h).min(self.height as f32) as usize;
+        let x_start = (x * s).max(0.0) as usize;
+        let x_end = ((x + w) * s).min(pw as f32) as usize;
+        let y_start = (y * s).max(0.0) as usize;
+        let y_end = ((y + h) * s).min(ph as f32) as usize;
 
         let pixels = self.pixmap.pixels_mut();
         for py in y_start..y_end {
             for px in x_start..x_end {
-                let idx = py * self.width as usize + px;
+                let idx = py * pw as usize + px;
                 pixels[idx] = highlight;
             }
         }
     }
 
+    /// Fade a region towards the background color
+    fn dim_region(&mut self, x: f32, y: f32, w: f32, h: f32) {
+        let background = self.appearance.theme.background;
+        let s = self.scale;
+        let (pw, ph) = (self.pixmap.width(), self.pixmap.height());
+        let x_start = (x * s).max(0.0) as usize;
+        let x_end = ((x + w) * s).min(pw as f32) as usize;
+        let y_start = (y * s).max(0.0) as usize;
+        let y_end = ((y + h) * s).min(ph as f32) as usize;
+
+        // Keep a third of the original color
+        let mix = |value: u8, target: u8| ((value as u16 + target as u16 * 2) / 3) as u8;
+        let pixels = self.pixmap.pixels_mut();
+        for py in y_start..y_end {
+            for px in x_start..x_end {
+                let idx = py * pw as usize + px;
+                let p = pixels[idx];
+                pixels[idx] = ColorU8::from_rgba(
+                    mix(p.red(), background.red()),
+                    mix(p.green(), background.green()),
+                    mix(p.blue(), background.blue()),
+                    255,
+                )
+                .premultiply();
+            }
+        }
+    }
+
     fn draw_horizontal_line(&mut self, x: f32, y: f32, width: f32) {
-        let line_color = divider_color().premultiply();
-        let x_start = x.max(0.0) as usize;
-        let x_end = (x + width).min(self.width as f32) as usize;
-        let py = y as usize;
+        let line_color = self.appearance.theme.divider.premultiply();
+        let s = self.scale;
+        let (pw, ph) = (self.pixmap.width(), self.pixmap.height());
+        let x_start = (x * s).max(0.0) as usize;
+        let x_end = ((x + width) * s).min(pw as f32) as usize;
+        let y_start = (y * s) as usize;
+        // Keep the line 1 logical pixel thick
+        let y_end = (y_start + s.round().max(1.0) as usize).min(ph as usize);
 
-        if py < self.height as usize {
-            let pixels = self.pixmap.pixels_mut();
+        let pixels = self.pixmap.pixels_mut();
+        for py in y_start..y_end {
             for px in x_start..x_end {
-                let idx = py * self.width as usize + px;
+                let idx = py * pw as usize + px;
                 pixels[idx] = line_color;
             }
         }
     }
 
-    fn draw_rounded_rect(&mut self, x: f32, y: f32, w: f32, h: f32, radius: f32, fill: tiny_skia::ColorU8, border: tiny_skia::ColorU8) {
+    fn draw_rounded_rect(&mut self, rect: Rect, radius: f32, fill: ColorU8, border: ColorU8) {
+        let s = self.scale;
+        let (x, y, w, h) = (rect.x * s, rect.y * s, rect.width * s, rect.height * s);
+        let (pw, ph) = (self.pixmap.width(), self.pixmap.height());
         let x_start = x.max(0.0) as i32;
-        let x_end = (x + w).min(self.width as f32) as i32;
+        let x_end = (x + w).min(pw as f32) as i32;
         let y_start = y.max(0.0) as i32;
-        let y_end = (y + h).min(self.height as f32) as i32;
+        let y_end = (y + h).min(ph as f32) as i32;
 
         let pixels = self.pixmap.pixels_mut();
-        let r = radius;
+        let r = radius * s;
+        // Border thickness, scaled so keycaps keep the same look on HiDPI
+        let bw = 1.5 * s;
 
         for py in y_start..y_end {
             for px in x_start..x_end {
@@ -286,92 +1375,239 @@ impl CharRefUI {
                 if local_x < r && local_y < r {
                     let dist = in_corner(r, r);
                     inside = dist <= r;
-                    on_border = dist > r - 1.5 && dist <= r;
+                    on_border = dist > r - bw && dist <= r;
                 }
                 // Top-right corner
                 else if local_x > w - r && local_y < r {
                     let dist = in_corner(w - r, r);
                     inside = dist <= r;
-                    on_border = dist > r - 1.5 && dist <= r;
+                    on_border = dist > r - bw && dist <= r;
                 }
                 // Bottom-left corner
                 else if local_x < r && local_y > h - r {
                     let dist = in_corner(r, h - r);
                     inside = dist <= r;
-                    on_border = dist > r - 1.5 && dist <= r;
+                    on_border = dist > r - bw && dist <= r;
                 }
                 // Bottom-right corner
                 else if local_x > w - r && local_y > h - r {
                     let dist = in_corner(w - r, h - r);
                     inside = dist <= r;
-                    on_border = dist > r - 1.5 && dist <= r;
+                    on_border = dist > r - bw && dist <= r;
                 }
                 // Edges (not corners)
                 else {
-                    on_border = local_x < 1.0 || local_x > w - 1.5 || local_y < 1.0 || local_y > h - 1.5;
+                    on_border = local_x < s || local_x > w - bw || local_y < s || local_y > h - bw;
                 }
 
                 if inside {
-                    let idx = (py as usize) * self.width as usize + (px as usize);
+                    let idx = (py as usize) * pw as usize + (px as usize);
                     pixels[idx] = if on_border { border.premultiply() } else { fill.premultiply() };
                 }
             }
         }
     }
 
+    /// Width of a keycap for the given label, from the shaped text width.
+    /// Keycaps are never narrower than they are tall, so single narrow
+    /// labels like "i" or "'" still look like keys.
+    fn keycap_width(&mut self, text: &str, is_modifier: bool) -> f32 {
+        let font_size = self.keycap_font_size(is_modifier);
+        let padding_x = if is_modifier { 6.0 } else { 8.0 };
+        let text_width = self.measure_text(text, font_size);
+        (text_width + padding_x * 2.0).max(KEYCAP_HEIGHT)
+    }
+
     /// Draw a keycap-styled box with text inside
+    fn keycap_font_size(&self, is_modifier: bool) -> f32 {
+        if is_modifier {
+            self.appearance.key_font_size * 0.75
+        } else {
+            self.appearance.key_font_size
+        }
+    }
+
     fn draw_keycap(&mut self, text: &str, x: f32, y: f32, is_modifier: bool) -> f32 {
-        let font_size = if is_modifier { 12.0 } else { 16.0 };
-        let padding_x = if is_modifier { 6.0 } else { 8.0 };
-        let padding_y = 4.0;
-        let height = 24.0;
+        self.draw_keycap_lit(text, x, y, is_modifier, false)
+    }
 
-        // Estimate text width (rough approximation based on character count)
-        let char_width = if is_modifier { 7.0 } else { 10.0 };
-        let text_width = text.chars().count() as f32 * char_width;
-        let width = text_width + padding_x * 2.0;
+    /// Keycap with an accent border and label when `lit` (key is held)
+    fn draw_keycap_lit(&mut self, text: &str, x: f32, y: f32, is_modifier: bool, lit: bool) -> f32 {
+        let font_size = self.keycap_font_size(is_modifier);
+        let padding_y = 4.0;
+        let height = KEYCAP_HEIGHT;
+        let width = self.keycap_width(text, is_modifier);
+        let text_width = self.measure_text(text, font_size);
 
         // Draw the keycap background
-        let bg = if is_modifier { modifier_keycap_bg() } else { keycap_bg() };
-        self.draw_rounded_rect(x, y, width, height, 4.0, bg, keycap_border());
+        let theme = &self.appearance.theme;
+        let bg = if is_modifier { theme.modifier_keycap_bg } else { theme.keycap_bg };
+        let border = if lit { theme.accent } else { theme.keycap_border };
+        self.draw_rounded_rect(Rect { x, y, width, height }, 4.0, bg, border);
 
         // Draw the text centered in the keycap
-        let text_color = if is_modifier { modifier_text() } else { accent_color() };
+        let theme = &self.appearance.theme;
+        let text_color = if is_modifier && !lit { theme.modifier_text } else { theme.accent };
         let text_y = y + padding_y;
-        let text_x = x + padding_x;
+        let text_x = x + (width - text_width) / 2.0;
         self.draw_text_colored(text, text_x, text_y, font_size, text_color);
 
         // Return the width so caller knows where to position next element
         width
     }
 
-    fn draw_text_colored(&mut self, text: &str, x: f32, y: f32, size: f32, color: Color) {
-        use cosmic_text::Color as CosmicColor;
+    /// Shape text at the given logical font size, caching the buffer.
+    /// Returns the cache key.
+    fn shape_text(&mut self, text: &str, size: f32, family: Option<&str>) -> TextKey {
+        let physical_size = size * self.scale;
+        let key = (text.to_string(), physical_size.to_bits(), family.map(String::from));
 
-        // Create a buffer for this text
-        let metrics = Metrics::new(size, size * 1.4);
-        let mut buffer = Buffer::new(&mut self.font_system, metrics);
-        buffer.set_hinting(&mut self.font_system, Hinting::Enabled);
+        if !self.text_cache.contains_key(&key) {
+            if self.text_cache.len() >= TEXT_CACHE_LIMIT {
+                self.text_cache.clear();
+            }
 
-        // Borrow the buffer with the font system
-        let mut buffer_ref = buffer.borrow_with(&mut self.font_system);
+            let metrics = Metrics::new(physical_size, physical_size * 1.4);
+            let mut buffer = Buffer::new(&mut self.font_system, metrics);
+            buffer.set_hinting(&mut self.font_system, Hinting::Enabled);
 
-        // Set buffer size and text
-        buffer_ref.set_size(Some(self.width as f32), Some(self.height as f32));
-        buffer_ref.set_text(text, &Attrs::new(), Shaping::Advanced, None);
+            // Borrow the buffer with the font system
+            let mut buffer_ref = buffer.borrow_with(&mut self.font_system);
 
-        // Shape the text
-        buffer_ref.shape_until_scroll(false);
+            // Unbounded size so the layout reflects the natural width of the text
+            buffer_ref.set_size(None, None);
+            let attrs = match family {
+                Some(name) => Attrs::new().family(Family::Name(name)),
+                None => Attrs::new(),
+            };
+            buffer_ref.set_text(text, &attrs, Shaping::Advanced, None);
 
-        // Convert tiny_skia Color to CosmicColor
-        let text_color = CosmicColor::rgb(
-            (color.red() * 255.0) as u8,
-            (color.green() * 255.0) as u8,
-            (color.blue() * 255.0) as u8
-        );
+            // Shape the text
+            buffer_ref.shape_until_scroll(false);
+
+            self.text_cache.insert(key.clone(), buffer);
+        }
+
+        key
+    }
+
+    /// Measure the logical width of a single line of interface text
+    fn measure_text(&mut self, text: &str, size: f32) -> f32 {
+        let family = self.appearance.ui_font.clone();
+        self.measure_text_with_family(text, size, family.as_deref())
+    }
+
+    fn measure_text_with_family(&mut self, text: &str, size: f32, family: Option<&str>) -> f32 {
+        let key = self.shape_text(text, size, family);
+        let physical_width = self.text_cache[&key]
+            .layout_runs()
+            .map(|run| run.line_w)
+            .fold(0.0, f32::max);
+        physical_width / self.scale
+    }
+
+    /// Find out which font can render a result character. Shaping replaces
+    /// uncovered characters with glyph 0 (.notdef, usually a blank box); in
+    /// that case scan the whole font database, since cosmic-text only falls
+    /// back to a fixed list of fonts per script.
+    fn glyph_coverage(&mut self, text: &str) -> GlyphCoverage {
+        if let Some(coverage) = self.glyph_coverage.get(text) {
+            return coverage.clone();
+        }
+
+        // Emoji are drawn in color when a color emoji font has them
+        if let Some(emoji_family) = self.emoji_family.clone().filter(|_| is_emoji(text)) {
+            if !self.has_notdef(text, Some(&emoji_family)) {
+                let coverage = GlyphCoverage::Family(emoji_family);
+                self.glyph_coverage.insert(text.to_string(), coverage.clone());
+                return coverage;
+            }
+        }
+
+        let family = self.appearance.char_font.clone();
+        let coverage = if !self.has_notdef(text, family.as_deref()) {
+            GlyphCoverage::Default
+        } else {
+            let chars: Vec<char> = text.chars().collect();
+            let faces: Vec<_> = self.font_system
+                .db()
+                .faces()
+                .filter_map(|face| Some((face.id, face.weight, face.families.first()?.0.clone())))
+                .collect();
+
+            let covering = faces.into_iter().find_map(|(id, weight, name)| {
+                let font = self.font_system.get_font(id, weight)?;
+                let charmap = font.as_swash().charmap();
+                chars.iter().all(|&c| charmap.map(c) != 0).then_some(name)
+            });
+
+            let codepoints: Vec<String> = chars.iter().map(|c| format!("U+{:04X}", *c as u32)).collect();
+            match covering {
+                Some(name) => {
+                    eprintln!("Using font '{}' for {} ({})", name, text, codepoints.join(" "));
+                    GlyphCoverage::Family(name)
+                }
+                None => {
+                    eprintln!("No installed font covers {} ({})", text, codepoints.join(" "));
+                    GlyphCoverage::Missing
+                }
+            }
+        };
+
+        self.glyph_coverage.insert(text.to_string(), coverage.clone());
+        coverage
+    }
+
+    /// Whether shaping text with this family leaves any character uncovered
+    fn has_notdef(&mut self, text: &str, family: Option<&str>) -> bool {
+        let key = self.shape_text(text, self.appearance.char_font_size, family);
+        self.text_cache[&key]
+            .layout_runs()
+            .any(|run| run.glyphs.iter().any(|glyph| glyph.glyph_id == 0))
+    }
+
+    /// Family to draw a result character with, None if no font covers it
+    fn glyph_family(&mut self, text: &str) -> Option<Option<String>> {
+        match self.glyph_coverage(text) {
+            GlyphCoverage::Default => Some(self.appearance.char_font.clone()),
+            GlyphCoverage::Family(name) => Some(Some(name)),
+            GlyphCoverage::Missing => None,
+        }
+    }
+
+    /// Width of the large result character, or of the "no font" marker
+    fn glyph_width(&mut self, text: &str) -> f32 {
+        match self.glyph_family(text) {
+            Some(family) => self.measure_text_with_family(text, self.appearance.char_font_size, family.as_deref()),
+            None => {
+                let label = codepoint_label(text);
+                self.measure_text(&label, 11.0).max(self.measure_text("no font", 11.0))
+            }
+        }
+    }
+
+    fn draw_text_colored(&mut self, text: &str, x: f32, y: f32, size: f32, color: ColorU8) {
+        let family = self.appearance.ui_font.clone();
+        self.draw_text_with_family(text, x, y, size, color, family.as_deref());
+    }
+
+    fn draw_text_with_family(&mut self, text: &str, x: f32, y: f32, size: f32, color: ColorU8, family: Option<&str>) {
+        use cosmic_text::Color as CosmicColor;
+
+        let key = self.shape_text(text, size, family);
+
+        // Scale position to physical pixels
+        let s = self.scale;
+        let (x, y) = (x * s, y * s);
+        let (pw, ph) = (self.pixmap.width() as i32, self.pixmap.height() as i32);
+
+        // Convert tiny_skia ColorU8 to CosmicColor
+        let text_color = CosmicColor::rgba(color.red(), color.green(), color.blue(), color.alpha());
 
         // Use buffer.draw() which handles glyph rasterization internally
-        buffer_ref.draw(&mut self.swash_cache, text_color, |px: i32, py: i32, w: u32, h: u32, color: CosmicColor| {
+        let buffer = &self.text_cache[&key];
+        let pixmap = &mut self.pixmap;
+        buffer.draw(&mut self.font_system, &mut self.swash_cache, text_color, |px: i32, py: i32, w: u32, h: u32, color: CosmicColor| {
             // px, py are pixel positions from cosmic-text, offset them to our desired location
             let pixel_x = (x as i32) + px;
             let pixel_y = (y as i32) + py;
@@ -382,15 +1618,15 @@ impl CharRefUI {
             }
 
             // Check bounds
-            if pixel_x < 0 || pixel_x >= self.width as i32 || pixel_y < 0 || pixel_y >= self.height as i32 {
+            if pixel_x < 0 || pixel_x >= pw || pixel_y < 0 || pixel_y >= ph {
                 return;
             }
 
             // Alpha-blend the glyph pixel with the background
-            let idx = (pixel_y * self.width as i32 + pixel_x) as usize;
+            let idx = (pixel_y * pw + pixel_x) as usize;
             let alpha = color.a() as f32 / 255.0;
             if alpha > 0.0 {
-                let bg = self.pixmap.pixels()[idx];
+                let bg = pixmap.pixels()[idx];
                 let bg_r = bg.red() as f32;
                 let bg_g = bg.green() as f32;
                 let bg_b = bg.blue() as f32;
@@ -401,70 +1637,93 @@ impl CharRefUI {
                 let out_r = (fg_r * alpha + bg_r * (1.0 - alpha)) as u8;
                 let out_g = (fg_g * alpha + bg_g * (1.0 - alpha)) as u8;
                 let out_b = (fg_b * alpha + bg_b * (1.0 - alpha)) as u8;
-                self.pixmap.pixels_mut()[idx] =
+                pixmap.pixels_mut()[idx] =
                     tiny_skia::ColorU8::from_rgba(out_r, out_g, out_b, 255).premultiply();
             }
         });
     }
 
-    fn draw_result(&mut self, entry: &ComposeEntry, x: f32, y: f32, row_height: f32, is_copied: bool, is_hovered: bool) {
+    fn draw_result(&mut self, entry: &ComposeEntry, layout: &RowLayout, x: f32, y: f32, row_height: f32, state: RowState) {
         // Draw subtle highlight background for hover or copied state
-        if is_copied {
+        let theme = self.appearance.theme.clone();
+        if state.copied {
             // Copied: slightly brighter highlight
-            let copied_color = tiny_skia::ColorU8::from_rgba(42, 42, 48, 255);
-            self.draw_row_highlight(0.0, y + 3.0, self.width as f32, row_height, copied_color);
-        } else if is_hovered {
+            self.draw_row_highlight(0.0, y + 3.0, self.width as f32, row_height, theme.copied_highlight);
+        } else if state.hovered {
             // Hover: subtle highlight
-            self.draw_row_highlight(0.0, y + 3.0, self.width as f32, row_height, hover_highlight());
+            self.draw_row_highlight(0.0, y + 3.0, self.width as f32, row_height, theme.hover_highlight);
         }
 
-        // Draw character (large and prominent) - 28px
-        self.draw_text_colored(&entry.character, x, y, 28.0, text_primary());
+        // Draw character (large and prominent), or mark it when no font has it
+        let glyph = display_text(&entry.character);
+        match self.glyph_family(&glyph) {
+            Some(family) => {
+                let char_size = self.appearance.char_font_size;
+                self.draw_text_with_family(&glyph, x, y, char_size, theme.text_primary, family.as_deref());
+            }
+            None => {
+                let label = codepoint_label(&entry.character);
+                self.draw_text_colored(&label, x, y + 2.0, 11.0, theme.text_secondary);
+                self.draw_text_colored("no font", x, y + 16.0, 11.0, theme.text_tertiary);
+            }
+        }
 
-        // Fixed column positions for vertical alignment
+        // Column positions shared by all rows for vertical alignment
         let keycap_y = y + 9.0;
-        let col_modifier = x + 45.0;    // Modifier keycap (e.g., AltGr, AltGr-Shift)
-        let col_plus = x + 138.0;       // "+" symbol
-        let col_key1 = x + 155.0;       // First key keycap
-        let col_arrow = x + 192.0;      // "→" symbol (dead keys)
-        let col_key2 = x + 215.0;       // Second key keycap (dead keys)
-
-        let symbol_color = connector_color();
-
-        // Parse key sequence - formats:
-        // Direct: "AltGr-w" or "Shift-a"
-        // Dead key: "AltGr-`  e" (double space separates steps)
-
-        // Check if this is a dead key sequence (has double space)
-        if let Some(space_pos) = entry.key_sequence.find("  ") {
-            // Dead key sequence: "AltGr-`  e"
-            let first_part = &entry.key_sequence[..space_pos];
-            let second_part = entry.key_sequence[space_pos..].trim();
-
-            // Parse first part (e.g., "AltGr-`")
-            if let Some(dash_pos) = first_part.rfind('-') {
-                let modifier = &first_part[..dash_pos];
-                let key1 = &first_part[dash_pos + 1..];
-
-                // Draw in fixed columns
-                self.draw_keycap(modifier, col_modifier, keycap_y, true);
-                self.draw_text_colored("+", col_plus, keycap_y + 4.0, 14.0, symbol_color);
-                self.draw_keycap(key1, col_key1, keycap_y, false);
-                self.draw_text_colored("→", col_arrow, keycap_y + 3.0, 14.0, symbol_color);
-                self.draw_keycap(second_part, col_key2, keycap_y, false);
-            }
-        } else if let Some(dash_pos) = entry.key_sequence.rfind('-') {
-            // Direct combination: "AltGr-w"
-            let modifier = &entry.key_sequence[..dash_pos];
-            let key = &entry.key_sequence[dash_pos + 1..];
-
-            // Draw in fixed columns
-            self.draw_keycap(modifier, col_modifier, keycap_y, true);
-            self.draw_text_colored("+", col_plus, keycap_y + 4.0, 14.0, symbol_color);
-            self.draw_keycap(key, col_key1, keycap_y, false);
-        } else {
-            // Fallback: just draw as keycap
-            self.draw_keycap(&entry.key_sequence, col_modifier, keycap_y, false);
+        let col_modifier = x + layout.col_modifier;    // Modifier keycap (e.g., AltGr, AltGr-Shift)
+        let col_plus = x + layout.col_plus;            // "+" symbol
+        let col_key1 = x + layout.col_key1;            // First key keycap
+        let col_arrow = x + layout.col_arrow;          // "→" symbol (dead keys)
+        let col_key2 = x + layout.col_key2;            // Second key keycap (dead keys)
+
+        let symbol_color = theme.connector;
+
+        match split_key_sequence(&entry.key_sequence) {
+            (Some(modifier), key1, second) => {
+                // Lit rows: the held modifiers apply to the first key
+                self.draw_keycap_lit(modifier, col_modifier, keycap_y, true, state.lit);
+                self.draw_text_colored("+", col_plus, keycap_y + 4.0, SYMBOL_FONT_SIZE, symbol_color);
+                self.draw_keycap_lit(key1, col_key1, keycap_y, false, state.lit);
+                // Dead key sequence: "AltGr-`  e"
+                if let Some(steps) = second {
+                    self.draw_text_colored("→", col_arrow, keycap_y + 3.0, SYMBOL_FONT_SIZE, symbol_color);
+                    self.draw_steps(steps, col_key2, keycap_y);
+                }
+            }
+            (None, "", _) => {
+                // Only in the list so a Compose rule can be added for it
+                self.draw_text_colored("no route", col_modifier, keycap_y + 4.0, SYMBOL_FONT_SIZE, theme.text_tertiary);
+            }
+            (None, key, second) => {
+                // Fallback: just draw as keycap
+                self.draw_keycap(key, col_modifier, keycap_y, false);
+                // A letter followed by a combining key ("e  AltGr-'"), or a
+                // Compose sequence
+                if let Some(steps) = second {
+                    self.draw_text_colored("→", col_arrow, keycap_y + 3.0, SYMBOL_FONT_SIZE, symbol_color);
+                    self.draw_steps(steps, col_key2, keycap_y);
+                }
+            }
+        }
+
+        // Other routes are listed in the detail panel
+        if !entry.alternatives.is_empty() {
+            self.draw_text_colored(&more_label(entry), x + layout.col_more, keycap_y + 4.0, SYMBOL_FONT_SIZE, theme.text_tertiary);
         }
     }
 }
+
+/// An installed color emoji font, e.g. "Noto Color Emoji"
+fn find_emoji_family(font_system: &FontSystem) -> Option<String> {
+    let families: Vec<String> = font_system
+        .db()
+        .faces()
+        .filter_map(|face| face.families.first().map(|(name, _)| name.clone()))
+        .filter(|name| name.contains("Emoji"))
+        .collect();
+    families
+        .iter()
+        .find(|name| name.contains("Color"))
+        .or(families.first())
+        .cloned()
+}
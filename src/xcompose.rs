@@ -0,0 +1,239 @@
+use crate::composefile::{self, ComposeRule};
+use crate::keyboard::locale;
+use std::env;
+use std::path::PathBuf;
+use unicode_normalization::UnicodeNormalization;
+use xkbcommon::xkb;
+
+/// Keysyms that stand for an accent in Compose sequences, following the
+/// system table (' for acute, " for diaeresis, ...)
+const MARK_KEYSYMS: &[(char, &str)] = &[
+    ('\u{301}', "apostrophe"),
+    ('\u{300}', "grave"),
+    ('\u{302}', "asciicircum"),
+    ('\u{308}', "quotedbl"),
+    ('\u{303}', "asciitilde"),
+    ('\u{327}', "comma"),
+    ('\u{328}', "semicolon"),
+    ('\u{30C}', "less"),
+    ('\u{306}', "U"),
+    ('\u{304}', "minus"),
+    ('\u{307}', "period"),
+    ('\u{30A}', "o"),
+    ('\u{30B}', "equal"),
+    ('\u{323}', "exclam"),
+    ('\u{309}', "question"),
+];
+
+// Words in character names that don't help as a mnemonic
+const FILLER_WORDS: &[&str] = &["LATIN", "SMALL", "CAPITAL", "LETTER", "WITH", "SIGN", "SYMBOL", "AND", "OF", "THE"];
+
+/// A Compose rule for a character that can't be typed yet, shown for
+/// confirmation before it is written
+pub struct RuleProposal {
+    pub character: String,
+    keysyms: Vec<xkb::Keysym>,
+    // The line as it will appear in the file
+    pub line: String,
+    pub path: PathBuf,
+}
+
+/// Pick a Compose sequence for a character: a mnemonic from its accents or
+/// its name that neither shadows nor is shadowed by an existing rule
+pub fn propose(character: &str) -> Result<RuleProposal, String> {
+    let rules = composefile::load_rules();
+    if rules.iter().any(|rule| rule.text == character) {
+        return Err(format!("'{}' already has a Compose rule; it needs a Compose key (e.g. compose:ralt)", character));
+    }
+
+    let keysyms = candidates(character)
+        .into_iter()
+        .find(|candidate| !conflicts(candidate, &rules))
+        .ok_or_else(|| format!("No free Compose sequence found for '{}'", character))?;
+    let line = rule_line(&keysyms, character);
+    let path = rule_file(
+        composefile::user_compose_file(),
+        env::var_os("XCOMPOSEFILE").filter(|v| !v.is_empty()).map(PathBuf::from),
+        env::var_os("HOME").map(PathBuf::from),
+    )
+    .ok_or("HOME is not set")?;
+
+    Ok(RuleProposal { character: character.to_string(), keysyms, line, path })
+}
+
+/// The user's Compose file that xkbcommon reads now. Only without one is a
+/// new file started, at $XCOMPOSEFILE or ~/.XCompose: a missing
+/// $XCOMPOSEFILE is skipped, so a new one there would hide ~/.XCompose.
+fn rule_file(in_use: Option<PathBuf>, xcomposefile: Option<PathBuf>, home: Option<PathBuf>) -> Option<PathBuf> {
+    in_use.or(xcomposefile).or_else(|| home.map(|home| home.join(".XCompose")))
+}
+
+/// Append the rule to the user's Compose file, after checking that
+/// xkbcommon compiles the result and that the sequence types the character.
+/// An existing file is first copied to <file>.bak. Returns a summary.
+pub fn write(proposal: &RuleProposal) -> Result<String, String> {
+    let path = &proposal.path;
+    let existing = match std::fs::read_to_string(path) {
+        Ok(contents) => Some(contents),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+    };
+
+    // A new file replaces the locale's table, which was the one in use; it
+    // has to include it, or all the default sequences would stop working
+    let mut contents = existing.clone().unwrap_or_else(|| "include \"%L\"\n".to_string());
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    contents.push_str(&format!("\n# Added by kbdviz\n{}\n", proposal.line));
+    validate(&contents, proposal)?;
+
+    let mut summary = format!("Added {} to {}", proposal.line, path.display());
+    if existing.is_some() {
+        let mut backup = path.clone().into_os_string();
+        backup.push(".bak");
+        std::fs::copy(path, &backup).map_err(|e| format!("Failed to back up {}: {}", path.display(), e))?;
+        summary.push_str(&format!(" (backup in {})", PathBuf::from(backup).display()));
+    }
+    std::fs::write(path, contents).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(summary)
+}
+
+/// Compile the new file contents and run the sequence through them
+fn validate(contents: &str, proposal: &RuleProposal) -> Result<(), String> {
+    let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+    let locale = locale().to_string_lossy().into_owned();
+    let table = xkb::compose::Table::new_from_buffer(
+        &context,
+        contents,
+        &locale,
+        xkb::compose::FORMAT_TEXT_V1,
+        xkb::compose::COMPILE_NO_FLAGS,
+    )
+    .map_err(|_| "The new Compose file doesn't compile".to_string())?;
+
+    let mut state = xkb::compose::State::new(&table, xkb::compose::STATE_NO_FLAGS);
+    for &keysym in &proposal.keysyms {
+        state.feed(keysym);
+    }
+    if state.status() == xkb::compose::Status::Composed && state.utf8().as_deref() == Some(proposal.character.as_str()) {
+        Ok(())
+    } else {
+        Err(format!("{} doesn't type '{}' after all", proposal.line, proposal.character))
+    }
+}
+
+/// Candidate sequences, best mnemonic first, all starting with Multi_key
+fn candidates(character: &str) -> Vec<Vec<xkb::Keysym>> {
+    let mut candidates: Vec<Vec<xkb::Keysym>> = Vec::new();
+
+    // Accent keys then the letter, or the other way around: ' e → é
+    let nfd: Vec<char> = character.nfd().collect();
+    if let Some((&base, marks)) = nfd.split_first() {
+        let marks: Option<Vec<xkb::Keysym>> = marks
+            .iter()
+            .map(|mark| MARK_KEYSYMS.iter().find(|(m, _)| m == mark).map(|(_, name)| keysym(name)))
+            .collect();
+        let base = char_keysym(base);
+        if let (Some(marks), Some(base)) = (marks.filter(|marks| !marks.is_empty()), base) {
+            candidates.push(marks.iter().copied().chain([base]).collect());
+            candidates.push([base].into_iter().chain(marks.iter().copied()).collect());
+        }
+    }
+
+    // Letters from the name: initials ("black heart" → b h), then growing
+    // prefixes of each word ("rocket" → r o, r o c, ...)
+    let name: Vec<String> = character
+        .chars()
+        .filter_map(|c| unicode_names2::name(c).map(|name| name.to_string()))
+        .collect();
+    let words: Vec<String> = name
+        .join(" ")
+        .split([' ', '-'])
+        .filter(|word| !FILLER_WORDS.contains(word))
+        .map(str::to_lowercase)
+        .collect();
+    let initials: String = words.iter().filter_map(|word| word.chars().next()).collect();
+    if initials.chars().count() >= 2 {
+        candidates.extend(ascii_keysyms(&initials));
+    }
+    for word in &words {
+        for length in 2..=word.chars().count().min(5) {
+            candidates.extend(ascii_keysyms(&word.chars().take(length).collect::<String>()));
+        }
+    }
+
+    // Always available as a last resort: the code point in hex
+    let hex: String = character.chars().map(|c| format!("{:x}", c as u32)).collect();
+    candidates.extend(ascii_keysyms(&format!("u{}", hex)));
+
+    let multi_key = xkb::Keysym::Multi_key;
+    candidates.into_iter().map(|keysyms| [multi_key].into_iter().chain(keysyms).collect()).collect()
+}
+
+/// A candidate clashes with a rule when either is a prefix of the other:
+/// the shorter one would always win or never finish
+fn conflicts(candidate: &[xkb::Keysym], rules: &[ComposeRule]) -> bool {
+    rules.iter().any(|rule| rule.keysyms.starts_with(candidate) || candidate.starts_with(&rule.keysyms))
+}
+
+/// Keysyms for a string of ASCII letters and digits, None for anything else
+fn ascii_keysyms(text: &str) -> Option<Vec<xkb::Keysym>> {
+    text.chars()
+        .map(|c| c.is_ascii_alphanumeric().then(|| keysym(&c.to_string())))
+        .collect()
+}
+
+fn char_keysym(c: char) -> Option<xkb::Keysym> {
+    let keysym = xkb::utf32_to_keysym(c as u32);
+    (keysym != xkb::Keysym::NoSymbol).then_some(keysym)
+}
+
+fn keysym(name: &str) -> xkb::Keysym {
+    xkb::keysym_from_name(name, xkb::KEYSYM_NO_FLAGS)
+}
+
+/// `<Multi_key> <apostrophe> <q> : "q́" # LATIN SMALL LETTER Q + COMBINING ACUTE ACCENT`
+fn rule_line(keysyms: &[xkb::Keysym], character: &str) -> String {
+    let sequence: Vec<String> = keysyms.iter().map(|&keysym| format!("<{}>", xkb::keysym_get_name(keysym))).collect();
+    let escaped = character.replace('\\', "\\\\").replace('"', "\\\"");
+    let names: Vec<String> = character
+        .chars()
+        .map(|c| unicode_names2::name(c).map_or_else(|| format!("U+{:04X}", c as u32), |name| name.to_string()))
+        .collect();
+    format!("{} : \"{}\" # {}", sequence.join(" "), escaped, names.join(" + "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(names: &[&str]) -> ComposeRule {
+        ComposeRule { keysyms: names.iter().map(|name| keysym(name)).collect(), text: "x".to_string() }
+    }
+
+    #[test]
+    fn accents_come_first() {
+        let first = &candidates("q\u{301}")[0];
+        assert_eq!(rule_line(first, "q\u{301}"), "<Multi_key> <apostrophe> <q> : \"q\u{301}\" # LATIN SMALL LETTER Q + COMBINING ACUTE ACCENT");
+    }
+
+    #[test]
+    fn prefixes_of_existing_rules_conflict() {
+        let rules = [rule(&["Multi_key", "r", "o"])];
+        assert!(conflicts(&[xkb::Keysym::Multi_key, keysym("r")], &rules));
+        assert!(conflicts(&[xkb::Keysym::Multi_key, keysym("r"), keysym("o"), keysym("c")], &rules));
+        assert!(!conflicts(&[xkb::Keysym::Multi_key, keysym("r"), keysym("c")], &rules));
+    }
+
+    #[test]
+    fn adds_rules_to_the_compose_file_in_use() {
+        let home = Some(PathBuf::from("/home/me"));
+        let in_use = Some(PathBuf::from("/home/me/.config/XCompose"));
+        // A missing $XCOMPOSEFILE must not hide the file xkbcommon reads
+        assert_eq!(rule_file(in_use.clone(), Some(PathBuf::from("/tmp/missing")), home.clone()), in_use);
+        assert_eq!(rule_file(None, Some(PathBuf::from("/tmp/missing")), home.clone()), Some(PathBuf::from("/tmp/missing")));
+        assert_eq!(rule_file(None, None, home), Some(PathBuf::from("/home/me/.XCompose")));
+        assert_eq!(rule_file(None, None, None), None);
+    }
+}